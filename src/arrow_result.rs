@@ -0,0 +1,508 @@
+use arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array,
+    Int64Array, Int8Array, ListArray, MapArray, StringArray, StructArray,
+};
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::iter::FromIterator;
+use std::sync::Arc;
+
+use client::*;
+use error::*;
+use model::*;
+use value::*;
+
+impl ColumnType {
+    // Date, timestamp and decimal values are kept as strings as returned by the API
+    pub fn to_arrow_data_type(&self) -> DataType {
+        match self {
+            &ColumnType::Boolean => DataType::Boolean,
+            &ColumnType::TinyInt => DataType::Int8,
+            &ColumnType::SmallInt => DataType::Int16,
+            &ColumnType::Int => DataType::Int32,
+            &ColumnType::BigInt => DataType::Int64,
+            &ColumnType::Real => DataType::Float32,
+            &ColumnType::Double => DataType::Float64,
+            &ColumnType::Varbinary => DataType::Binary,
+            &ColumnType::Array(ref element_type) => {
+                DataType::List(Arc::new(list_item_field(element_type)))
+            }
+            &ColumnType::Map(ref key_type, ref value_type) => {
+                DataType::Map(Arc::new(map_entries_field(key_type, value_type)), false)
+            }
+            &ColumnType::Row(ref fields) => DataType::Struct(row_fields(fields)),
+            _ => DataType::Utf8,
+        }
+    }
+}
+
+impl ResultSchema {
+    pub fn to_arrow_schema(&self) -> Schema {
+        Schema::new(
+            self.columns
+                .iter()
+                .map(|column| {
+                    Field::new(&column.name, column.column_type.to_arrow_data_type(), true)
+                })
+                .collect::<Vec<Field>>(),
+        )
+    }
+}
+
+fn list_item_field(element_type: &ColumnType) -> Field {
+    Field::new("item", element_type.to_arrow_data_type(), true)
+}
+
+fn map_entries_field(key_type: &ColumnType, value_type: &ColumnType) -> Field {
+    Field::new(
+        "entries",
+        DataType::Struct(map_entry_fields(key_type, value_type)),
+        false,
+    )
+}
+
+fn map_entry_fields(key_type: &ColumnType, value_type: &ColumnType) -> Fields {
+    Fields::from(vec![
+        Field::new("key", key_type.to_arrow_data_type(), false),
+        Field::new("value", value_type.to_arrow_data_type(), true),
+    ])
+}
+
+// Anonymous fields of a row are named like `field0`
+fn row_fields(fields: &[ResultColumn]) -> Fields {
+    Fields::from(
+        fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let name = if field.name.is_empty() {
+                    format!("field{}", i)
+                } else {
+                    field.name.clone()
+                };
+                Field::new(&name, field.column_type.to_arrow_data_type(), true)
+            })
+            .collect::<Vec<Field>>(),
+    )
+}
+
+fn unexpected_value(column_type: &ColumnType) -> TreasureDataError {
+    TreasureDataError::InvalidArgumentError(InvalidArgument {
+        key: column_type.to_string(),
+        value: "Value can't be converted".to_string(),
+    })
+}
+
+fn integers(
+    column_type: &ColumnType,
+    values: Vec<Value>,
+) -> Result<Vec<Option<i64>>, TreasureDataError> {
+    values
+        .into_iter()
+        .map(|value| match column_type.convert(value)? {
+            Value::Nil => Ok(None),
+            Value::Integer(Integer::I64(x)) => Ok(Some(x)),
+            _ => Err(unexpected_value(column_type)),
+        })
+        .collect()
+}
+
+fn narrow<T>(
+    column_type: &ColumnType,
+    xs: Vec<Option<i64>>,
+) -> Result<Vec<Option<T>>, TreasureDataError>
+where
+    T: ::std::convert::TryFrom<i64>,
+{
+    xs.into_iter()
+        .map(|x| match x {
+            Some(x) => T::try_from(x)
+                .map(Some)
+                .map_err(|_| unexpected_value(column_type)),
+            None => Ok(None),
+        })
+        .collect()
+}
+
+fn floats(
+    column_type: &ColumnType,
+    values: Vec<Value>,
+) -> Result<Vec<Option<f64>>, TreasureDataError> {
+    values
+        .into_iter()
+        .map(|value| match column_type.convert(value)? {
+            Value::Nil => Ok(None),
+            Value::Float(Float::F32(x)) => Ok(Some(x as f64)),
+            Value::Float(Float::F64(x)) => Ok(Some(x)),
+            _ => Err(unexpected_value(column_type)),
+        })
+        .collect()
+}
+
+// Builds an Arrow array of `column_type` from the values of a column
+fn build_array(
+    column_type: &ColumnType,
+    values: Vec<Value>,
+) -> Result<ArrayRef, TreasureDataError> {
+    let array: ArrayRef = match column_type {
+        &ColumnType::Boolean => Arc::new(BooleanArray::from(
+            values
+                .into_iter()
+                .map(|value| match column_type.convert(value)? {
+                    Value::Nil => Ok(None),
+                    Value::Boolean(x) => Ok(Some(x)),
+                    _ => Err(unexpected_value(column_type)),
+                })
+                .collect::<Result<Vec<Option<bool>>, TreasureDataError>>()?,
+        )),
+        &ColumnType::TinyInt => Arc::new(Int8Array::from(narrow::<i8>(
+            column_type,
+            integers(column_type, values)?,
+        )?)),
+        &ColumnType::SmallInt => Arc::new(Int16Array::from(narrow::<i16>(
+            column_type,
+            integers(column_type, values)?,
+        )?)),
+        &ColumnType::Int => Arc::new(Int32Array::from(narrow::<i32>(
+            column_type,
+            integers(column_type, values)?,
+        )?)),
+        &ColumnType::BigInt => Arc::new(Int64Array::from(integers(column_type, values)?)),
+        &ColumnType::Real => Arc::new(Float32Array::from(
+            floats(column_type, values)?
+                .into_iter()
+                .map(|x| x.map(|x| x as f32))
+                .collect::<Vec<Option<f32>>>(),
+        )),
+        &ColumnType::Double => Arc::new(Float64Array::from(floats(column_type, values)?)),
+        &ColumnType::Varbinary => Arc::new(BinaryArray::from_iter(
+            values
+                .into_iter()
+                .map(|value| match column_type.convert(value)? {
+                    Value::Nil => Ok(None),
+                    Value::Binary(x) => Ok(Some(x)),
+                    _ => Err(unexpected_value(column_type)),
+                })
+                .collect::<Result<Vec<Option<Vec<u8>>>, TreasureDataError>>()?,
+        )),
+        &ColumnType::Array(ref element_type) => {
+            let mut offsets = vec![0i32];
+            let mut validity = vec![];
+            let mut elements = vec![];
+            for value in values {
+                match column_type.convert(value)? {
+                    Value::Nil => validity.push(false),
+                    Value::Array(xs) => {
+                        validity.push(true);
+                        elements.extend(xs);
+                    }
+                    _ => return Err(unexpected_value(column_type)),
+                }
+                offsets.push(elements.len() as i32);
+            }
+            Arc::new(ListArray::try_new(
+                Arc::new(list_item_field(element_type)),
+                OffsetBuffer::new(offsets.into()),
+                build_array(element_type, elements)?,
+                Some(NullBuffer::from(validity)),
+            )?)
+        }
+        &ColumnType::Map(ref key_type, ref value_type) => {
+            let mut offsets = vec![0i32];
+            let mut validity = vec![];
+            let mut keys = vec![];
+            let mut items = vec![];
+            for value in values {
+                match column_type.convert(value)? {
+                    Value::Nil => validity.push(false),
+                    Value::Map(kvs) => {
+                        validity.push(true);
+                        for (k, v) in kvs {
+                            keys.push(k);
+                            items.push(v);
+                        }
+                    }
+                    _ => return Err(unexpected_value(column_type)),
+                }
+                offsets.push(keys.len() as i32);
+            }
+            let entries = StructArray::try_new(
+                map_entry_fields(key_type, value_type),
+                vec![
+                    build_array(key_type, keys)?,
+                    build_array(value_type, items)?,
+                ],
+                None,
+            )?;
+            Arc::new(MapArray::try_new(
+                Arc::new(map_entries_field(key_type, value_type)),
+                OffsetBuffer::new(offsets.into()),
+                entries,
+                Some(NullBuffer::from(validity)),
+                false,
+            )?)
+        }
+        &ColumnType::Row(ref fields) => {
+            let mut validity = vec![];
+            let mut children: Vec<Vec<Value>> = fields.iter().map(|_| vec![]).collect();
+            for value in values {
+                match column_type.convert(value)? {
+                    Value::Nil => {
+                        validity.push(false);
+                        for child in children.iter_mut() {
+                            child.push(Value::Nil);
+                        }
+                    }
+                    Value::Array(xs) => {
+                        validity.push(true);
+                        for (child, x) in children.iter_mut().zip(xs) {
+                            child.push(x);
+                        }
+                    }
+                    _ => return Err(unexpected_value(column_type)),
+                }
+            }
+            Arc::new(StructArray::try_new(
+                row_fields(fields),
+                fields
+                    .iter()
+                    .zip(children)
+                    .map(|(field, child)| build_array(&field.column_type, child))
+                    .collect::<Result<Vec<ArrayRef>, TreasureDataError>>()?,
+                Some(NullBuffer::from(validity)),
+            )?)
+        }
+        _ => Arc::new(StringArray::from_iter(
+            values
+                .into_iter()
+                .map(|value| match ColumnType::Varchar.convert(value)? {
+                    Value::Nil => Ok(None),
+                    Value::String(x) => Ok(Some(x)),
+                    _ => Err(unexpected_value(column_type)),
+                })
+                .collect::<Result<Vec<Option<String>>, TreasureDataError>>()?,
+        )),
+    };
+    Ok(array)
+}
+
+// Reads rows and groups them into record batches of up to `batch_size` rows
+pub struct RecordBatches {
+    rows: QueryRows,
+    schema: ResultSchema,
+    arrow_schema: SchemaRef,
+    batch_size: usize,
+}
+
+impl RecordBatches {
+    pub fn new(
+        rows: QueryRows,
+        schema: ResultSchema,
+        batch_size: usize,
+    ) -> Result<RecordBatches, TreasureDataError> {
+        if batch_size == 0 {
+            return Err(TreasureDataError::InvalidArgumentError(InvalidArgument {
+                key: "batch_size".to_string(),
+                value: batch_size.to_string(),
+            }));
+        }
+        Ok(RecordBatches {
+            rows: rows,
+            arrow_schema: Arc::new(schema.to_arrow_schema()),
+            schema: schema,
+            batch_size: batch_size,
+        })
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.arrow_schema.clone()
+    }
+
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>, TreasureDataError> {
+        let num_columns = self.schema.columns.len();
+        let mut columns: Vec<Vec<Value>> = (0..num_columns).map(|_| vec![]).collect();
+        let mut num_rows = 0;
+        while num_rows < self.batch_size {
+            let row = match self.rows.next() {
+                Some(row) => row?,
+                None => break,
+            };
+            if row.len() > num_columns {
+                return Err(TreasureDataError::InvalidArgumentError(InvalidArgument {
+                    key: "row".to_string(),
+                    value: format!(
+                        "{} values for {} columns in the result schema",
+                        row.len(),
+                        num_columns
+                    ),
+                }));
+            }
+            // Missing trailing values are null
+            let mut row = row.into_iter();
+            for column in columns.iter_mut() {
+                column.push(row.next().unwrap_or(Value::Nil));
+            }
+            num_rows += 1;
+        }
+        if num_rows == 0 {
+            return Ok(None);
+        }
+        let arrays = self
+            .schema
+            .columns
+            .iter()
+            .zip(columns)
+            .map(|(column, values)| build_array(&column.column_type, values))
+            .collect::<Result<Vec<ArrayRef>, TreasureDataError>>()?;
+        Ok(Some(RecordBatch::try_new(
+            self.arrow_schema.clone(),
+            arrays,
+        )?))
+    }
+}
+
+impl Iterator for RecordBatches {
+    type Item = Result<RecordBatch, TreasureDataError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_batch() {
+            Ok(Some(batch)) => Some(Ok(batch)),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+impl<R> Client<R>
+where
+    R: RequestExecutor,
+{
+    // Reads the result of a finished job as Arrow record batches typed from the
+    // result schema of the job
+    pub fn job_result_arrow(
+        &self,
+        job_id: u64,
+        batch_size: usize,
+    ) -> Result<RecordBatches, TreasureDataError> {
+        let schema = match self.job(job_id)?.result_schema() {
+            Some(schema) => schema,
+            None => {
+                return Err(TreasureDataError::InvalidArgumentError(InvalidArgument {
+                    key: "job_id".to_string(),
+                    value: format!("{} doesn't have a result schema", job_id),
+                }))
+            }
+        };
+        let (response, _) = self.job_result(job_id)?;
+        let rows = QueryRows::new(Box::new(GzDecoder::new(response)), schema.clone());
+        RecordBatches::new(rows, schema, batch_size)
+    }
+
+    // Same as `job_result_arrow` for a file written by `download_job_result`
+    pub fn job_result_file_arrow(
+        &self,
+        in_file: &File,
+        schema: &ResultSchema,
+        batch_size: usize,
+    ) -> Result<RecordBatches, TreasureDataError> {
+        let rows = QueryRows::new(
+            Box::new(GzDecoder::new(in_file.try_clone()?)),
+            schema.clone(),
+        );
+        RecordBatches::new(rows, schema.clone(), batch_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mockito;
+    use self::mockito::mock;
+
+    use arrow::array::{Array, Int64Array, ListArray, StringArray};
+    use arrow::datatypes::DataType;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use rmpv::Value as RmpvValue;
+    use std::io::prelude::*;
+
+    use client::Client;
+
+    #[test]
+    fn job_result_arrow() {
+        let _mock_job = mock("GET", "/v3/job/show/12345")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                r#"{"job_id":"12345", "type":"presto", "query":"select 1", "status":"success",
+                    "url":"https://console.treasuredata.com/jobs/12345",
+                    "created_at":"2016-01-01 00:00:00 UTC", "start_at":"2016-01-01 00:00:01 UTC",
+                    "end_at":"2016-01-01 00:00:10 UTC", "cpu_time":null, "result_size":20,
+                    "hive_result_schema":"[[\"name\",\"varchar\"],[\"cnt\",\"bigint\"],[\"tags\",\"array(varchar)\"]]",
+                    "priority":0, "retry_limit":0, "duration":9, "num_records":3}"#,
+            )
+            .create();
+
+        let mut msgpack = vec![];
+        let rows: Vec<Vec<RmpvValue>> = vec![
+            vec![
+                RmpvValue::from("a"),
+                RmpvValue::from(1),
+                RmpvValue::Array(vec![RmpvValue::from("x")]),
+            ],
+            vec![RmpvValue::from("b"), RmpvValue::from("2"), RmpvValue::Nil],
+            vec![
+                RmpvValue::Nil,
+                RmpvValue::Nil,
+                RmpvValue::Array(vec![RmpvValue::from("y"), RmpvValue::from("z")]),
+            ],
+        ];
+        for row in rows {
+            ::rmpv::encode::write_value(&mut msgpack, &RmpvValue::Array(row)).unwrap();
+        }
+        let mut e = GzEncoder::new(vec![], Compression::default());
+        e.write_all(&msgpack).unwrap();
+        let _mock_result = mock("GET", "/v3/job/result/12345?format=msgpack_gz")
+            .with_status(200)
+            .with_body(e.finish().unwrap())
+            .create();
+
+        let mut client = Client::new("1234abcd");
+        client.endpoint(&mockito::server_url());
+
+        let batches = client.job_result_arrow(12345, 2).unwrap();
+        assert_eq!(
+            &DataType::Int64,
+            batches.schema().field_with_name("cnt").unwrap().data_type()
+        );
+        let batches = batches.map(|x| x.unwrap()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![2, 1],
+            batches.iter().map(|x| x.num_rows()).collect::<Vec<_>>()
+        );
+
+        let names = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!("b", names.value(1));
+        let counts = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(vec![Some(1), Some(2)], counts.iter().collect::<Vec<_>>());
+        assert!(batches[1].column(0).is_null(0));
+        let tags = batches[1]
+            .column(2)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(2, tags.value(0).len());
+
+        assert!(client.job_result_arrow(12345, 0).is_err());
+    }
+}
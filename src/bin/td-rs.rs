@@ -0,0 +1,813 @@
+extern crate flate2;
+extern crate rustc_serialize;
+extern crate td_client;
+
+use flate2::read::GzDecoder;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::process;
+use std::str::FromStr;
+
+use td_client::client::*;
+use td_client::error::*;
+use td_client::model::*;
+use td_client::value::*;
+
+const USAGE: &'static str = "Usage: td-rs [options] <command> [arguments]
+
+Options:
+  -k, --apikey <apikey>      API key (default: $TD_API_KEY)
+  -e, --endpoint <url>       API endpoint (default: $TD_API_SERVER or https://api.treasuredata.com)
+  -f, --format <format>      Output format: table, csv or json (default: table)
+  -h, --help                 Show this message
+
+Commands:
+  db:list
+  db:create <db>
+  db:delete <db>
+  table:list <db>
+  table:create <db> <table>
+  table:delete <db> <table>
+  table:swap <db> <table1> <table2>
+  table:rename <db> <table> <new_name>
+  table:tail <db> <table> [-n <count>]
+  table:import <db> <table> <file.msgpack.gz>
+  schema:show <db> <table>
+  schema:add <db> <table> <column:type>...
+  job:list [-s <status>] [-l <limit>]
+  job:show <job_id>
+  job:status <job_id>
+  job:kill <job_id>
+  job:result <job_id> [-o <file.msgpack.gz>]
+  query -d <db> [-T hive|presto] [-w] <query>";
+
+// Options which take a value. Others are flags
+const VALUE_OPTIONS: &'static [(&'static str, &'static str)] = &[
+    ("-k", "--apikey"),
+    ("-e", "--endpoint"),
+    ("-f", "--format"),
+    ("-n", "--count"),
+    ("-s", "--status"),
+    ("-l", "--limit"),
+    ("-o", "--output"),
+    ("-d", "--database"),
+    ("-T", "--type"),
+];
+
+const FLAG_OPTIONS: &'static [(&'static str, &'static str)] = &[("-w", "--wait"), ("-h", "--help")];
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Api(TreasureDataError),
+}
+
+impl From<TreasureDataError> for CliError {
+    fn from(err: TreasureDataError) -> CliError {
+        CliError::Api(err)
+    }
+}
+
+impl From<InvalidArgument> for CliError {
+    fn from(err: InvalidArgument) -> CliError {
+        CliError::Usage(format!("Invalid {}: {}", err.key, err.value))
+    }
+}
+
+impl From<::std::io::Error> for CliError {
+    fn from(err: ::std::io::Error) -> CliError {
+        CliError::Api(TreasureDataError::IoError(err))
+    }
+}
+
+// Parsed command line. Options can appear anywhere and are keyed by their long name
+struct Args {
+    positionals: Vec<String>,
+    options: BTreeMap<&'static str, String>,
+    flags: Vec<&'static str>,
+}
+
+impl Args {
+    fn parse(args: Vec<String>) -> Result<Args, CliError> {
+        let mut parsed = Args {
+            positionals: vec![],
+            options: BTreeMap::new(),
+            flags: vec![],
+        };
+        let mut iter = args.into_iter();
+        while let Some(arg) = iter.next() {
+            if let Some(&(_, long)) = VALUE_OPTIONS
+                .iter()
+                .find(|&&(short, long)| arg == short || arg == long)
+            {
+                match iter.next() {
+                    Some(value) => parsed.options.insert(long, value),
+                    None => return Err(CliError::Usage(format!("{} requires a value", arg))),
+                };
+            } else if let Some(&(_, long)) = FLAG_OPTIONS
+                .iter()
+                .find(|&&(short, long)| arg == short || arg == long)
+            {
+                parsed.flags.push(long);
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                return Err(CliError::Usage(format!("Unknown option {}", arg)));
+            } else {
+                parsed.positionals.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, long: &str) -> Option<&str> {
+        self.options.get(long).map(|x| x.as_str())
+    }
+
+    fn flag(&self, long: &str) -> bool {
+        self.flags.iter().any(|x| *x == long)
+    }
+
+    // Positional arguments following the command
+    fn arguments(&self, names: &[&str]) -> Result<Vec<&str>, CliError> {
+        let arguments: Vec<&str> = self
+            .positionals
+            .iter()
+            .skip(1)
+            .map(|x| x.as_str())
+            .collect();
+        if arguments.len() != names.len() {
+            return Err(CliError::Usage(format!(
+                "{} takes <{}>",
+                self.positionals[0],
+                names.join("> <")
+            )));
+        }
+        Ok(arguments)
+    }
+
+    fn u64_option(&self, long: &str) -> Result<Option<u64>, CliError> {
+        match self.option(long) {
+            Some(x) => x
+                .parse::<u64>()
+                .map(Some)
+                .map_err(|_| CliError::Usage(format!("{} must be a number: {}", long, x))),
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_u64(name: &str, s: &str) -> Result<u64, CliError> {
+    s.parse::<u64>()
+        .map_err(|_| CliError::Usage(format!("{} must be a number: {}", name, s)))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = InvalidArgument;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(InvalidArgument {
+                key: "format".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace("\"", "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn print_table<W: Write>(out: &mut W, header: &[String], rows: &[Vec<String>]) -> io::Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|x| x.chars().count()).collect();
+    for row in rows {
+        for (width, item) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(item.chars().count());
+        }
+    }
+    let line = |items: Vec<&str>| {
+        let items: Vec<String> = items
+            .iter()
+            .zip(widths.iter())
+            .map(|(item, width)| format!("{}{}", item, " ".repeat(width - item.chars().count())))
+            .collect();
+        format!("| {} |", items.join(" | "))
+    };
+    let border = format!(
+        "+{}+",
+        widths
+            .iter()
+            .map(|width| "-".repeat(width + 2))
+            .collect::<Vec<String>>()
+            .join("+")
+    );
+    writeln!(out, "{}", border)?;
+    writeln!(out, "{}", line(header.iter().map(|x| x.as_str()).collect()))?;
+    writeln!(out, "{}", border)?;
+    for row in rows {
+        writeln!(out, "{}", line(row.iter().map(|x| x.as_str()).collect()))?;
+    }
+    writeln!(out, "{}", border)?;
+    writeln!(
+        out,
+        "{} row{} in set",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" }
+    )
+}
+
+// Prints each row as soon as it's given. Only the table format keeps rows
+// until `finish` since it needs the widths of all of them
+struct RowPrinter<W: Write> {
+    out: W,
+    format: Format,
+    header: Vec<String>,
+    table_rows: Vec<Vec<String>>,
+    count: usize,
+}
+
+impl<W: Write> RowPrinter<W> {
+    fn new(mut out: W, format: Format, header: Vec<String>) -> io::Result<RowPrinter<W>> {
+        match format {
+            Format::Table => (),
+            Format::Csv => writeln!(
+                out,
+                "{}",
+                header
+                    .iter()
+                    .map(|x| csv_field(x))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            )?,
+            Format::Json => write!(out, "[")?,
+        }
+        Ok(RowPrinter {
+            out: out,
+            format: format,
+            header: header,
+            table_rows: vec![],
+            count: 0,
+        })
+    }
+
+    fn print(&mut self, row: Vec<Value>) -> io::Result<()> {
+        match self.format {
+            Format::Table => self
+                .table_rows
+                .push(row.iter().map(value_to_string).collect()),
+            Format::Csv => writeln!(
+                self.out,
+                "{}",
+                row.iter()
+                    .map(|x| csv_field(&value_to_string(x)))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            )?,
+            Format::Json => {
+                // Written in the order of the header, unlike a canonical JSON map
+                let object = self
+                    .header
+                    .iter()
+                    .zip(row)
+                    .map(|(k, v)| {
+                        format!(
+                            "{}:{}",
+                            Value::String(k.clone()).to_canonical_json(),
+                            v.to_canonical_json()
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join(",");
+                writeln!(self.out, "{}", if self.count == 0 { "" } else { "," })?;
+                write!(self.out, "  {{{}}}", object)?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        match self.format {
+            Format::Table => print_table(&mut self.out, &self.header, &self.table_rows)?,
+            Format::Csv => (),
+            Format::Json => {
+                if self.count > 0 {
+                    writeln!(self.out)?;
+                }
+                writeln!(self.out, "]")?;
+            }
+        }
+        Ok(self.out)
+    }
+}
+
+fn print_rows(format: Format, header: &[&str], rows: Vec<Vec<Value>>) -> Result<(), CliError> {
+    let stdout = io::stdout();
+    let mut printer = RowPrinter::new(
+        stdout.lock(),
+        format,
+        header.iter().map(|x| x.to_string()).collect(),
+    )?;
+    for row in rows {
+        printer.print(row)?;
+    }
+    printer.finish()?.flush()?;
+    Ok(())
+}
+
+// Top-level strings are printed as they are and nil as empty
+fn value_to_string(value: &Value) -> String {
+    match *value {
+        Value::Nil => "".to_string(),
+        Value::String(ref x) => x.clone(),
+        ref x => x.to_string(),
+    }
+}
+
+fn text<T: ToString + ?Sized>(x: &T) -> Value {
+    Value::String(x.to_string())
+}
+
+fn opt_text<T: ToString>(x: &Option<T>) -> Value {
+    x.as_ref().map_or(Value::Nil, |x| text(x))
+}
+
+fn number(x: u64) -> Value {
+    Value::Integer(Integer::U64(x))
+}
+
+fn opt_number(x: Option<u64>) -> Value {
+    x.map_or(Value::Nil, number)
+}
+
+fn print_job_result<I>(format: Format, schema: &ResultSchema, rows: I) -> Result<(), CliError>
+where
+    I: Iterator<Item = Result<Vec<Value>, TreasureDataError>>,
+{
+    let mut rows = rows.peekable();
+    // The schema isn't available for some job types
+    let header: Vec<String> = if schema.columns.is_empty() {
+        let len = match rows.peek() {
+            Some(&Ok(ref row)) => row.len(),
+            _ => 0,
+        };
+        (0..len).map(|i| format!("_c{}", i)).collect()
+    } else {
+        schema.columns.iter().map(|x| x.name.clone()).collect()
+    };
+    let stdout = io::stdout();
+    let mut printer = RowPrinter::new(stdout.lock(), format, header)?;
+    for row in rows {
+        printer.print(row?)?;
+    }
+    printer.finish()?.flush()?;
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), CliError> {
+    let apikey = match args.option("--apikey") {
+        Some(apikey) => apikey.to_string(),
+        None => env::var("TD_API_KEY")
+            .map_err(|_| CliError::Usage("API key is required".to_string()))?,
+    };
+    let mut client = Client::new(&apikey);
+    match args.option("--endpoint") {
+        Some(endpoint) => {
+            client.endpoint(endpoint);
+        }
+        None => {
+            if let Ok(endpoint) = env::var("TD_API_SERVER") {
+                client.endpoint(&endpoint);
+            }
+        }
+    }
+    let format = Format::from_str(args.option("--format").unwrap_or("table"))?;
+
+    let command = match args.positionals.first() {
+        Some(command) => command.as_str(),
+        None => return Err(CliError::Usage("Command is required".to_string())),
+    };
+    match command {
+        "db:list" => {
+            args.arguments(&[])?;
+            let rows = client
+                .databases()?
+                .iter()
+                .map(|db| {
+                    vec![
+                        text(&db.name),
+                        number(db.count),
+                        text(&db.permission),
+                        text(&db.created_at),
+                    ]
+                })
+                .collect();
+            print_rows(format, &["name", "count", "permission", "created_at"], rows)?;
+        }
+        "db:create" => {
+            let a = args.arguments(&["db"])?;
+            client.create_database(a[0])?;
+            println!("Database '{}' is created.", a[0]);
+        }
+        "db:delete" => {
+            let a = args.arguments(&["db"])?;
+            client.delete_database(a[0])?;
+            println!("Database '{}' is deleted.", a[0]);
+        }
+        "table:list" => {
+            let a = args.arguments(&["db"])?;
+            let rows = client
+                .tables(a[0])?
+                .iter()
+                .map(|table| {
+                    vec![
+                        text(&table.name),
+                        number(table.count),
+                        opt_number(table.expire_days.map(u64::from)),
+                        opt_text(&table.last_import),
+                        text(&table.created_at),
+                    ]
+                })
+                .collect();
+            print_rows(
+                format,
+                &["name", "count", "expire_days", "last_import", "created_at"],
+                rows,
+            )?;
+        }
+        "table:create" => {
+            let a = args.arguments(&["db", "table"])?;
+            client.create_table(a[0], a[1])?;
+            println!("Table '{}.{}' is created.", a[0], a[1]);
+        }
+        "table:delete" => {
+            let a = args.arguments(&["db", "table"])?;
+            client.delete_table(a[0], a[1])?;
+            println!("Table '{}.{}' is deleted.", a[0], a[1]);
+        }
+        "table:swap" => {
+            let a = args.arguments(&["db", "table1", "table2"])?;
+            client.swap_table(a[0], a[1], a[2])?;
+            println!("'{}.{}' and '{}.{}' are swapped.", a[0], a[1], a[0], a[2]);
+        }
+        "table:rename" => {
+            let a = args.arguments(&["db", "table", "new_name"])?;
+            client.rename_table(a[0], a[1], a[2])?;
+            println!("'{}.{}' is renamed to '{}.{}'.", a[0], a[1], a[0], a[2]);
+        }
+        "table:tail" => {
+            let a = args.arguments(&["db", "table"])?;
+            let count = args.u64_option("--count")?;
+            let records = client.tail_table(a[0], a[1], count, None, None)?;
+            // Columns are the keys of all records in the order they appear
+            let mut header: Vec<String> = vec![];
+            for record in &records {
+                if let Value::Map(ref kvs) = *record {
+                    for &(ref k, _) in kvs {
+                        let k = value_to_string(k);
+                        if !header.contains(&k) {
+                            header.push(k);
+                        }
+                    }
+                }
+            }
+            let rows = records
+                .iter()
+                .map(|record| {
+                    header
+                        .iter()
+                        .map(|column| match *record {
+                            Value::Map(ref kvs) => kvs
+                                .iter()
+                                .find(|&&(ref k, _)| value_to_string(k) == *column)
+                                .map_or(Value::Nil, |&(_, ref v)| v.clone()),
+                            _ => Value::Nil,
+                        })
+                        .collect()
+                })
+                .collect();
+            let header: Vec<&str> = header.iter().map(|x| x.as_str()).collect();
+            print_rows(format, &header, rows)?;
+        }
+        "table:import" => {
+            let a = args.arguments(&["db", "table", "file.msgpack.gz"])?;
+            client.import_msgpack_gz_file_to_table(a[0], a[1], a[2], None)?;
+            println!("'{}' is imported to '{}.{}'.", a[2], a[0], a[1]);
+        }
+        "schema:show" => {
+            let a = args.arguments(&["db", "table"])?;
+            let table = client.table(a[0], a[1])?;
+            let schema = Json::from_str(&table.schema)
+                .map_err(|err| CliError::Api(TreasureDataError::JsonParseError(err)))?;
+            let rows = schema
+                .as_array()
+                .map(|columns| {
+                    columns
+                        .iter()
+                        .filter_map(|column| column.as_array())
+                        .map(|column| {
+                            column
+                                .iter()
+                                .take(2)
+                                .map(|x| text(x.as_string().unwrap_or("")))
+                                .collect()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            print_rows(format, &["name", "type"], rows)?;
+        }
+        "schema:add" => {
+            if args.positionals.len() < 4 {
+                return Err(CliError::Usage(
+                    "schema:add takes <db> <table> <column:type>...".to_string(),
+                ));
+            }
+            let mut columns = vec![];
+            for column in &args.positionals[3..] {
+                match column.find(':') {
+                    Some(i) => {
+                        columns.push((&column[..i], SchemaType::from_str(&column[i + 1..])?))
+                    }
+                    None => {
+                        return Err(CliError::Usage(format!(
+                            "Column must be <column:type>: {}",
+                            column
+                        )))
+                    }
+                }
+            }
+            client.append_schema(&args.positionals[1], &args.positionals[2], &columns)?;
+            println!(
+                "Schema of '{}.{}' is updated.",
+                args.positionals[1], args.positionals[2]
+            );
+        }
+        "job:list" => {
+            args.arguments(&[])?;
+            let status = match args.option("--status") {
+                Some(status) => match JobStatus::from_str(status) {
+                    Ok(JobStatus::Unknown(_)) | Err(_) => {
+                        return Err(CliError::Usage(format!("Invalid status: {}", status)))
+                    }
+                    Ok(status) => Some(status),
+                },
+                None => None,
+            };
+            let limit = args.u64_option("--limit")?.unwrap_or(20);
+            if limit == 0 {
+                return Ok(());
+            }
+            let rows = client
+                .jobs(status, Some(0), Some(limit - 1))?
+                .jobs
+                .iter()
+                .map(|job| {
+                    vec![
+                        number(job.job_id),
+                        text(&job.job_type),
+                        text(&job.status),
+                        opt_text(&job.database),
+                        text(&job.created_at),
+                        opt_number(job.duration),
+                    ]
+                })
+                .collect();
+            print_rows(
+                format,
+                &[
+                    "job_id",
+                    "type",
+                    "status",
+                    "database",
+                    "created_at",
+                    "duration",
+                ],
+                rows,
+            )?;
+        }
+        "job:show" => {
+            let a = args.arguments(&["job_id"])?;
+            let job = client.job(parse_u64("job_id", a[0])?)?;
+            let query = match job.query {
+                JobQuery::Query(ref q) => q.clone(),
+                JobQuery::Config(ref c) => c.to_string(),
+            };
+            let rows = vec![
+                vec![text("job_id"), number(job.job_id)],
+                vec![text("type"), text(&job.job_type)],
+                vec![text("status"), text(&job.status)],
+                vec![text("database"), opt_text(&job.database)],
+                vec![text("query"), text(&query)],
+                vec![text("url"), text(&job.url)],
+                vec![text("created_at"), text(&job.created_at)],
+                vec![text("start_at"), opt_text(&job.start_at)],
+                vec![text("end_at"), opt_text(&job.end_at)],
+                vec![text("num_records"), opt_number(job.num_records)],
+                vec![
+                    text("error"),
+                    opt_text(&job.debug.as_ref().and_then(|debug| debug.error_message())),
+                ],
+            ];
+            print_rows(format, &["key", "value"], rows)?;
+        }
+        "job:status" => {
+            let a = args.arguments(&["job_id"])?;
+            println!(
+                "{}",
+                client.job_status(parse_u64("job_id", a[0])?)?.to_string()
+            );
+        }
+        "job:kill" => {
+            let a = args.arguments(&["job_id"])?;
+            client.kill_job(parse_u64("job_id", a[0])?)?;
+            println!("Job {} is killed.", a[0]);
+        }
+        "job:result" => {
+            let a = args.arguments(&["job_id"])?;
+            let job_id = parse_u64("job_id", a[0])?;
+            match args.option("--output") {
+                Some(path) => {
+                    client.download_job_result(job_id, &File::create(path)?)?;
+                    println!("The result of job {} is written to '{}'.", job_id, path);
+                }
+                None => {
+                    let schema = client.job(job_id)?.result_schema().unwrap_or_default();
+                    let (response, _) = client.job_result(job_id)?;
+                    let rows = QueryRows::new(Box::new(GzDecoder::new(response)), schema.clone());
+                    print_job_result(format, &schema, rows)?
+                }
+            }
+        }
+        "query" => {
+            if args.positionals.len() != 2 {
+                return Err(CliError::Usage("query takes <query>".to_string()));
+            }
+            let database = args
+                .option("--database")
+                .ok_or(CliError::Usage("query requires -d <db>".to_string()))?;
+            let query_type = QueryType::from_str(args.option("--type").unwrap_or("presto"))?;
+            let query = &args.positionals[1];
+            if args.flag("--wait") {
+                // A failed job is returned as `TreasureDataError::JobFailed`, which exits with 1
+                let result = client.query(
+                    query_type,
+                    database,
+                    query,
+                    &QueryOptions::new().wait_interval_secs(2),
+                )?;
+                println!("Job {} is finished.", result.job_id);
+                print_job_result(format, &result.schema, result.rows)?;
+            } else {
+                let job_id =
+                    client.issue_job(query_type, database, query, None, None, None, None, None)?;
+                println!("Job {} is queued.", job_id);
+            }
+        }
+        _ => return Err(CliError::Usage(format!("Unknown command {}", command))),
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = Args::parse(env::args().skip(1).collect()).and_then(|args| {
+        if args.flag("--help") || args.positionals.is_empty() {
+            println!("{}", USAGE);
+            Ok(())
+        } else {
+            run(args)
+        }
+    });
+    match result {
+        Ok(()) => (),
+        Err(CliError::Usage(message)) => {
+            eprintln!("{}\nSee 'td-rs --help'.", message);
+            process::exit(1);
+        }
+        Err(CliError::Api(err)) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, CliError> {
+        Args::parse(args.iter().map(|x| x.to_string()).collect())
+    }
+
+    #[test]
+    fn parse_args() {
+        let args = parse(&[
+            "-d", "db0", "query", "--wait", "select 1", "--type", "hive", "-",
+        ])
+        .unwrap();
+        assert_eq!(vec!["query", "select 1", "-"], args.positionals);
+        assert_eq!(Some("db0"), args.option("--database"));
+        assert_eq!(Some("hive"), args.option("--type"));
+        assert_eq!(None, args.option("--format"));
+        assert!(args.flag("--wait"));
+        assert!(!args.flag("--help"));
+
+        // The last one wins
+        let args = parse(&["-f", "csv", "db:list", "--format", "json", "-h"]).unwrap();
+        assert_eq!(Some("json"), args.option("--format"));
+        assert!(args.flag("--help"));
+    }
+
+    #[test]
+    fn parse_invalid_args() {
+        match parse(&["table:tail", "db0", "tbl0", "-n"]) {
+            Err(CliError::Usage(message)) => assert_eq!("-n requires a value", message),
+            x => panic!("Unexpected result: {:?}", x.map(|x| x.positionals)),
+        }
+        match parse(&["db:list", "--verbose"]) {
+            Err(CliError::Usage(message)) => assert_eq!("Unknown option --verbose", message),
+            x => panic!("Unexpected result: {:?}", x.map(|x| x.positionals)),
+        }
+    }
+
+    #[test]
+    fn arguments() {
+        let args = parse(&["table:swap", "db0", "tbl0", "tbl1"]).unwrap();
+        assert_eq!(
+            vec!["db0", "tbl0", "tbl1"],
+            args.arguments(&["db", "table1", "table2"]).unwrap()
+        );
+        match args.arguments(&["db", "table"]) {
+            Err(CliError::Usage(message)) => assert_eq!("table:swap takes <db> <table>", message),
+            x => panic!("Unexpected result: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn u64_option() {
+        let args = parse(&["job:list", "-l", "5"]).unwrap();
+        assert_eq!(5, args.u64_option("--limit").unwrap().unwrap());
+        assert!(args.u64_option("--count").unwrap().is_none());
+
+        let args = parse(&["job:list", "--limit", "five"]).unwrap();
+        match args.u64_option("--limit") {
+            Err(CliError::Usage(message)) => assert_eq!("--limit must be a number: five", message),
+            x => panic!("Unexpected result: {:?}", x),
+        }
+    }
+
+    fn print(format: Format, header: &[&str], rows: Vec<Vec<Value>>) -> String {
+        let header = header.iter().map(|x| x.to_string()).collect();
+        let mut printer = RowPrinter::new(vec![], format, header).unwrap();
+        for row in rows {
+            printer.print(row).unwrap();
+        }
+        String::from_utf8(printer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn output_formats() {
+        let rows = || {
+            vec![
+                vec![text("a,b"), number(42), Value::Float(Float::F64(1.5))],
+                vec![Value::Nil, Value::Integer(Integer::I64(-1)), text("x")],
+            ]
+        };
+        assert_eq!(
+            "+-----+-----+-----+
+| s   | cnt | f   |
++-----+-----+-----+
+| a,b | 42  | 1.5 |
+|     | -1  | x   |
++-----+-----+-----+
+2 rows in set
+",
+            print(Format::Table, &["s", "cnt", "f"], rows())
+        );
+        assert_eq!(
+            "s,cnt,f\n\"a,b\",42,1.5\n,-1,x\n",
+            print(Format::Csv, &["s", "cnt", "f"], rows())
+        );
+        assert_eq!(
+            "[\n  {\"s\":\"a,b\",\"cnt\":42,\"f\":1.5},\n  {\"s\":null,\"cnt\":-1,\"f\":\"x\"}\n]\n",
+            print(Format::Json, &["s", "cnt", "f"], rows())
+        );
+        assert_eq!("[]\n", print(Format::Json, &["s"], vec![]));
+    }
+}
@@ -1,7 +1,9 @@
 use std::error::Error;
 use std::fmt;
 
-#[derive(Debug)]
+use model::{JobDebug, JobStatus};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct InvalidArgument {
     pub key: String,
     pub value: String,
@@ -34,6 +36,12 @@ pub enum TreasureDataError {
     ApiError(::reqwest::StatusCode, String),
     InvalidArgumentError(InvalidArgument),
     IoError(::std::io::Error),
+    // Job ID, its final status and the debug output
+    JobFailed(u64, JobStatus, JobDebug),
+    #[cfg(feature = "arrow")]
+    ArrowError(::arrow::error::ArrowError),
+    #[cfg(feature = "parquet")]
+    ParquetError(::parquet::errors::ParquetError),
 }
 
 impl From<::rustc_serialize::json::DecoderError> for TreasureDataError {
@@ -78,9 +86,52 @@ impl From<::chrono::ParseError> for TreasureDataError {
     }
 }
 
+#[cfg(feature = "arrow")]
+impl From<::arrow::error::ArrowError> for TreasureDataError {
+    fn from(err: ::arrow::error::ArrowError) -> TreasureDataError {
+        TreasureDataError::ArrowError(err)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<::parquet::errors::ParquetError> for TreasureDataError {
+    fn from(err: ::parquet::errors::ParquetError) -> TreasureDataError {
+        TreasureDataError::ParquetError(err)
+    }
+}
+
 impl fmt::Display for TreasureDataError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
+        match *self {
+            TreasureDataError::JsonDecodeError(ref x) => write!(f, "Failed to decode JSON: {}", x),
+            TreasureDataError::JsonParseError(ref x) => write!(f, "Failed to parse JSON: {}", x),
+            TreasureDataError::MsgpackDecodeError(ref x) => {
+                write!(f, "Failed to decode msgpack: {}", x)
+            }
+            TreasureDataError::MsgpackUnexpectedValueError(ref x) => {
+                write!(f, "Unexpected msgpack value: {}", x)
+            }
+            TreasureDataError::TimeStampParseError(ref x) => {
+                write!(f, "Failed to parse a timestamp: {}", x)
+            }
+            TreasureDataError::HttpError(ref x) => write!(f, "HTTP request failed: {}", x),
+            TreasureDataError::ApiError(status, ref body) => {
+                write!(f, "API error. status:{}, body:{}", status, body)
+            }
+            TreasureDataError::InvalidArgumentError(ref x) => write!(f, "{}", x),
+            TreasureDataError::IoError(ref x) => write!(f, "I/O error: {}", x),
+            TreasureDataError::JobFailed(job_id, ref status, ref debug) => {
+                write!(f, "Job {} finished with {}", job_id, status.to_string())?;
+                match debug.error_message() {
+                    Some(message) => write!(f, ": {}", message),
+                    None => Ok(()),
+                }
+            }
+            #[cfg(feature = "arrow")]
+            TreasureDataError::ArrowError(ref x) => write!(f, "Arrow error: {}", x),
+            #[cfg(feature = "parquet")]
+            TreasureDataError::ParquetError(ref x) => write!(f, "Parquet error: {}", x),
+        }
     }
 }
 
@@ -96,6 +147,11 @@ impl Error for TreasureDataError {
             TreasureDataError::ApiError(..) => None,
             TreasureDataError::InvalidArgumentError(ref x) => Some(x),
             TreasureDataError::IoError(ref x) => Some(x),
+            TreasureDataError::JobFailed(..) => None,
+            #[cfg(feature = "arrow")]
+            TreasureDataError::ArrowError(ref x) => Some(x),
+            #[cfg(feature = "parquet")]
+            TreasureDataError::ParquetError(ref x) => Some(x),
         }
     }
 }
@@ -1,18 +1,33 @@
+#[cfg(feature = "arrow")]
+extern crate arrow;
 extern crate chrono;
 extern crate flate2;
+#[cfg(feature = "testing")]
+extern crate http;
 extern crate reqwest;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "parquet")]
+extern crate parquet;
 extern crate regex;
 extern crate rmp;
 extern crate rmpv;
 extern crate rustc_serialize;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
 extern crate tempdir;
 
 pub mod error;
 pub mod model;
+pub mod progress;
 pub mod value;
 #[macro_use]
 mod json_helper;
 pub mod client;
 pub mod table_import;
+#[cfg(feature = "arrow")]
+pub mod arrow_result;
+#[cfg(feature = "parquet")]
+pub mod parquet_result;
+#[cfg(feature = "testing")]
+pub mod testing;
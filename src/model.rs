@@ -3,8 +3,9 @@ use rustc_serialize::*;
 use std::str::FromStr;
 
 use error::*;
+use value::*;
 
-#[derive(PartialEq, Eq, Debug, RustcEncodable)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct TimeStamp(DateTime<Utc>);
 
 impl FromStr for TimeStamp {
@@ -17,6 +18,17 @@ impl FromStr for TimeStamp {
     }
 }
 
+impl TimeStamp {
+    // Returns None when `secs` is out of the range of `DateTime`
+    pub fn from_unix_time(secs: i64) -> Option<TimeStamp> {
+        Utc.timestamp_opt(secs, 0).single().map(TimeStamp)
+    }
+
+    pub fn unix_time(&self) -> i64 {
+        self.0.timestamp()
+    }
+}
+
 impl ToString for TimeStamp {
     fn to_string(&self) -> String {
         let TimeStamp(datetime) = *self;
@@ -34,6 +46,13 @@ impl Decodable for TimeStamp {
     }
 }
 
+// Encoded in the same format as the API returns, so it can be decoded again
+impl Encodable for TimeStamp {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(&self.to_string())
+    }
+}
+
 #[derive(Debug, RustcDecodable, RustcEncodable)]
 pub struct Table {
     pub name: String,
@@ -45,6 +64,7 @@ pub struct Table {
     pub last_import: Option<TimeStamp>,
     pub last_log_timestamp: Option<TimeStamp>,
     pub expire_days: Option<u32>,
+    pub include_v: Option<bool>,
 }
 
 #[derive(Debug, RustcDecodable, RustcEncodable)]
@@ -59,7 +79,8 @@ pub struct Database {
     pub count: u64,
     pub created_at: TimeStamp,
     pub updated_at: TimeStamp,
-    pub permission: String,
+    pub permission: Permission,
+    pub description: Option<String>,
 }
 
 #[derive(Debug, RustcDecodable, RustcEncodable)]
@@ -67,13 +88,145 @@ pub struct Databases {
     pub databases: Vec<Database>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Permission {
+    Administrator,
+    FullAccess,
+    ImportOnly,
+    QueryOnly,
+    Unknown(String),
+}
+
+impl ToString for Permission {
+    fn to_string(&self) -> String {
+        match self {
+            &Permission::Administrator => "administrator".to_string(),
+            &Permission::FullAccess => "full_access".to_string(),
+            &Permission::ImportOnly => "import_only".to_string(),
+            &Permission::QueryOnly => "query_only".to_string(),
+            &Permission::Unknown(ref s) => s.clone(),
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = InvalidArgument;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "administrator" => Ok(Permission::Administrator),
+            "full_access" => Ok(Permission::FullAccess),
+            "import_only" => Ok(Permission::ImportOnly),
+            "query_only" => Ok(Permission::QueryOnly),
+            // Permissions added to the API later shouldn't break decoding databases
+            _ => Ok(Permission::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl Decodable for Permission {
+    fn decode<D: Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let field = d.read_str()?;
+        match field.parse() {
+            Ok(result) => Ok(result),
+            Err(_) => Err(d.error(&*format!("Could not parse '{}' as a Permission.", field))),
+        }
+    }
+}
+
+impl Encodable for Permission {
+    fn encode<S: Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(&self.to_string())
+    }
+}
+
+#[derive(Debug, RustcDecodable, RustcEncodable)]
+pub struct DatabaseAccess {
+    pub user: String,
+    pub permission: Permission,
+}
+
+#[derive(Debug, RustcDecodable, RustcEncodable)]
+pub struct DatabaseAccessList {
+    pub database: String,
+    pub access: Vec<DatabaseAccess>,
+}
+
+// Unset fields are left as they are
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseOptions {
+    pub description: Option<String>,
+}
+
+impl DatabaseOptions {
+    pub fn new() -> DatabaseOptions {
+        DatabaseOptions::default()
+    }
+
+    pub fn description(mut self, description: &str) -> DatabaseOptions {
+        self.description = Some(description.to_string());
+        self
+    }
+}
+
+#[derive(Debug, RustcDecodable, RustcEncodable)]
+pub struct User {
+    pub id: u64,
+    pub name: String,
+    pub email: String,
+    pub organization: Option<String>,
+    pub administrator: bool,
+    pub created_at: TimeStamp,
+    pub updated_at: TimeStamp,
+}
+
+#[derive(Debug, RustcDecodable, RustcEncodable)]
+pub struct Users {
+    pub users: Vec<User>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey(pub String);
+
+impl ToString for ApiKey {
+    fn to_string(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum JobStatus {
     Queued,
     Running,
     Success,
     Killed,
     Error,
+    Unknown(String),
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        match *self {
+            JobStatus::Queued | JobStatus::Running | JobStatus::Unknown(_) => false,
+            JobStatus::Success | JobStatus::Killed | JobStatus::Error => true,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        *self == JobStatus::Success
+    }
+}
+
+impl ToString for JobStatus {
+    fn to_string(&self) -> String {
+        match self {
+            &JobStatus::Queued => "queued".to_string(),
+            &JobStatus::Running => "running".to_string(),
+            &JobStatus::Success => "success".to_string(),
+            &JobStatus::Killed => "killed".to_string(),
+            &JobStatus::Error => "error".to_string(),
+            &JobStatus::Unknown(ref s) => s.clone(),
+        }
+    }
 }
 
 impl FromStr for JobStatus {
@@ -85,10 +238,75 @@ impl FromStr for JobStatus {
             "success" => Ok(JobStatus::Success),
             "killed" => Ok(JobStatus::Killed),
             "error" => Ok(JobStatus::Error),
-            _ => Err(json::DecoderError::ExpectedError(
-                "(queued|running|success|error|killed)".to_string(),
-                s.to_string(),
-            )),
+            // Statuses added to the API later shouldn't break decoding jobs
+            _ => Ok(JobStatus::Unknown(s.to_string())),
+        }
+    }
+}
+
+// Unlike `QueryType`, this needs to accept types of jobs which this library doesn't issue
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobType {
+    Hive,
+    Presto,
+    Pig,
+    BulkLoad,
+    BulkImport,
+    Export,
+    ResultExport,
+    PartialDelete,
+    Unknown(String),
+}
+
+impl JobType {
+    pub fn query_type(&self) -> Option<QueryType> {
+        match self {
+            &JobType::Hive => Some(QueryType::Hive),
+            &JobType::Presto => Some(QueryType::Presto),
+            &JobType::Pig => Some(QueryType::Pig),
+            _ => None,
+        }
+    }
+}
+
+impl ToString for JobType {
+    fn to_string(&self) -> String {
+        match self {
+            &JobType::Hive => "hive".to_string(),
+            &JobType::Presto => "presto".to_string(),
+            &JobType::Pig => "pig".to_string(),
+            &JobType::BulkLoad => "bulkload".to_string(),
+            &JobType::BulkImport => "bulk_import".to_string(),
+            &JobType::Export => "export".to_string(),
+            &JobType::ResultExport => "result_export".to_string(),
+            &JobType::PartialDelete => "partialdelete".to_string(),
+            &JobType::Unknown(ref s) => s.clone(),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for JobType {
+    fn from(s: &'a str) -> Self {
+        match s {
+            "hive" => JobType::Hive,
+            "presto" => JobType::Presto,
+            "pig" => JobType::Pig,
+            "bulkload" => JobType::BulkLoad,
+            "bulk_import" => JobType::BulkImport,
+            "export" => JobType::Export,
+            "result_export" => JobType::ResultExport,
+            "partialdelete" => JobType::PartialDelete,
+            _ => JobType::Unknown(s.to_string()),
+        }
+    }
+}
+
+impl From<QueryType> for JobType {
+    fn from(query_type: QueryType) -> Self {
+        match query_type {
+            QueryType::Hive => JobType::Hive,
+            QueryType::Presto => JobType::Presto,
+            QueryType::Pig => JobType::Pig,
         }
     }
 }
@@ -99,13 +317,43 @@ pub enum JobQuery {
     Config(json::Json),
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct JobDebug {
+    pub cmdout: Option<String>,
+    pub stderr: Option<String>,
+}
+
+impl JobDebug {
+    // Picks the line which most likely describes the failure from the logs.
+    // Presto reports `Query ... failed: ...` and Hive reports `FAILED: ...`
+    pub fn error_message(&self) -> Option<String> {
+        let pick_line = |log: &Option<String>| -> Option<String> {
+            log.as_ref().and_then(|log| {
+                let lines: Vec<&str> = log
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .collect();
+                lines
+                    .iter()
+                    .rev()
+                    .find(|line| line.contains("failed:") || line.contains("FAILED:"))
+                    .or(lines.last())
+                    .map(|line| line.to_string())
+            })
+        };
+        pick_line(&self.stderr).or(pick_line(&self.cmdout))
+    }
+}
+
 // We can't use RustcDecodable because `query` can have either string or object...
 #[derive(Debug)]
 pub struct Job {
     pub job_id: u64,
-    pub job_type: String,
+    pub job_type: JobType,
     pub query: JobQuery,
-    pub status: String,
+    pub status: JobStatus,
+    pub database: Option<String>,
     pub url: String,
     pub created_at: TimeStamp,
     pub start_at: Option<TimeStamp>,
@@ -116,6 +364,141 @@ pub struct Job {
     pub priority: u64,
     pub retry_limit: u64,
     pub duration: Option<u64>,
+    pub num_records: Option<u64>,
+    pub result: Option<String>,
+    pub linked_result_export_job_id: Option<u64>,
+    pub organization: Option<String>,
+    pub user_name: Option<String>,
+    pub debug: Option<JobDebug>,
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        self.status.is_finished()
+    }
+
+    pub fn result_schema(&self) -> Option<ResultSchema> {
+        self.hive_result_schema
+            .as_ref()
+            .map(|x| ResultSchema::from_hive_result_schema(x))
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobFilter {
+    pub page_size: u64,
+    pub status: Option<JobStatus>,
+    pub database: Option<String>,
+    pub job_type: Option<JobType>,
+    pub created_after: Option<TimeStamp>,
+    pub created_before: Option<TimeStamp>,
+}
+
+impl JobFilter {
+    pub fn new() -> JobFilter {
+        JobFilter {
+            page_size: 20,
+            status: None,
+            database: None,
+            job_type: None,
+            created_after: None,
+            created_before: None,
+        }
+    }
+
+    pub fn page_size(mut self, page_size: u64) -> JobFilter {
+        self.page_size = page_size;
+        self
+    }
+
+    pub fn status(mut self, status: JobStatus) -> JobFilter {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn database(mut self, database: &str) -> JobFilter {
+        self.database = Some(database.to_string());
+        self
+    }
+
+    pub fn job_type(mut self, job_type: JobType) -> JobFilter {
+        self.job_type = Some(job_type);
+        self
+    }
+
+    // Inclusive
+    pub fn created_after(mut self, created_after: TimeStamp) -> JobFilter {
+        self.created_after = Some(created_after);
+        self
+    }
+
+    // Exclusive
+    pub fn created_before(mut self, created_before: TimeStamp) -> JobFilter {
+        self.created_before = Some(created_before);
+        self
+    }
+
+    pub fn matches(&self, job: &Job) -> bool {
+        self.status.as_ref().map_or(true, |x| *x == job.status)
+            && self
+                .database
+                .as_ref()
+                .map_or(true, |x| job.database.as_ref() == Some(x))
+            && self.job_type.as_ref().map_or(true, |x| *x == job.job_type)
+            && self.created_after.map_or(true, |x| x <= job.created_at)
+            && self.created_before.map_or(true, |x| job.created_at < x)
+    }
+}
+
+// Options of `Client::query`
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    pub result_url: Option<String>,
+    pub priority: Option<u64>,
+    pub retry_limit: Option<u64>,
+    pub domain_key: Option<String>,
+    pub scheduled_time: Option<TimeStamp>,
+    pub wait_interval_secs: Option<u64>,
+}
+
+impl QueryOptions {
+    pub fn new() -> QueryOptions {
+        QueryOptions::default()
+    }
+
+    pub fn result_url(mut self, result_url: &str) -> QueryOptions {
+        self.result_url = Some(result_url.to_string());
+        self
+    }
+
+    pub fn priority(mut self, priority: u64) -> QueryOptions {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn retry_limit(mut self, retry_limit: u64) -> QueryOptions {
+        self.retry_limit = Some(retry_limit);
+        self
+    }
+
+    pub fn domain_key(mut self, domain_key: &str) -> QueryOptions {
+        self.domain_key = Some(domain_key.to_string());
+        self
+    }
+
+    pub fn scheduled_time(mut self, scheduled_time: TimeStamp) -> QueryOptions {
+        self.scheduled_time = Some(scheduled_time);
+        self
+    }
+
+    pub fn wait_interval_secs(mut self, wait_interval_secs: u64) -> QueryOptions {
+        self.wait_interval_secs = Some(wait_interval_secs);
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -126,7 +509,7 @@ pub struct Jobs {
     pub jobs: Vec<Job>,
 }
 
-#[derive(Debug, Clone, RustcDecodable, RustcEncodable)]
+#[derive(Debug, Clone, PartialEq, RustcDecodable, RustcEncodable)]
 pub enum QueryType {
     Hive,
     Presto,
@@ -158,7 +541,7 @@ impl FromStr for QueryType {
     }
 }
 
-#[derive(Debug, RustcDecodable, RustcEncodable)]
+#[derive(Debug, Clone, PartialEq, RustcDecodable, RustcEncodable)]
 pub enum SchemaType {
     Int,
     Long,
@@ -182,3 +565,1161 @@ impl ToString for SchemaType {
         }
     }
 }
+
+impl FromStr for SchemaType {
+    type Err = InvalidArgument;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "int" => Ok(SchemaType::Int),
+            "long" => Ok(SchemaType::Long),
+            "float" => Ok(SchemaType::Float),
+            "double" => Ok(SchemaType::Double),
+            "string" => Ok(SchemaType::String),
+            _ if s.starts_with("array<") && s.ends_with('>') => Ok(SchemaType::Array(Box::new(
+                SchemaType::from_str(&s[6..s.len() - 1])?,
+            ))),
+            _ => Err(InvalidArgument {
+                key: "schema_type".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CreateTableOptions {
+    pub expire_days: Option<u32>,
+    pub include_v: Option<bool>,
+    pub schema: Option<Vec<(String, SchemaType)>>,
+}
+
+impl CreateTableOptions {
+    pub fn new() -> CreateTableOptions {
+        CreateTableOptions::default()
+    }
+
+    pub fn expire_days(mut self, expire_days: u32) -> CreateTableOptions {
+        self.expire_days = Some(expire_days);
+        self
+    }
+
+    pub fn include_v(mut self, include_v: bool) -> CreateTableOptions {
+        self.include_v = Some(include_v);
+        self
+    }
+
+    pub fn schema(mut self, schema: &[(&str, SchemaType)]) -> CreateTableOptions {
+        self.schema = Some(
+            schema
+                .iter()
+                .map(|&(name, ref schema_type)| (name.to_string(), schema_type.clone()))
+                .collect(),
+        );
+        self
+    }
+}
+
+// Unset fields are left as they are
+#[derive(Debug, Clone, Default)]
+pub struct TableUpdate {
+    pub expire_days: Option<u32>,
+    pub include_v: Option<bool>,
+    pub detect_schema: Option<bool>,
+    pub schema: Option<Vec<(String, SchemaType)>>,
+}
+
+impl TableUpdate {
+    pub fn new() -> TableUpdate {
+        TableUpdate::default()
+    }
+
+    pub fn expire_days(mut self, expire_days: u32) -> TableUpdate {
+        self.expire_days = Some(expire_days);
+        self
+    }
+
+    pub fn include_v(mut self, include_v: bool) -> TableUpdate {
+        self.include_v = Some(include_v);
+        self
+    }
+
+    // Let the API guess the schema from imported records
+    pub fn detect_schema(mut self, detect_schema: bool) -> TableUpdate {
+        self.detect_schema = Some(detect_schema);
+        self
+    }
+
+    pub fn schema(mut self, schema: &[(&str, SchemaType)]) -> TableUpdate {
+        self.schema = Some(
+            schema
+                .iter()
+                .map(|&(name, ref schema_type)| (name.to_string(), schema_type.clone()))
+                .collect(),
+        );
+        self
+    }
+}
+
+// We can't use RustcDecodable because of `type`
+#[derive(Debug)]
+pub struct Schedule {
+    pub name: String,
+    pub cron: Option<String>,
+    pub timezone: String,
+    pub delay: i64,
+    pub created_at: TimeStamp,
+    // Schedules can also be created for types of jobs which `QueryType` doesn't cover
+    pub job_type: JobType,
+    pub query: String,
+    pub database: String,
+    pub user_name: Option<String>,
+    pub priority: i64,
+    pub retry_limit: u64,
+    pub result_url: Option<String>,
+    pub next_time: Option<TimeStamp>,
+}
+
+// Unset fields are left as they are on update
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleParams {
+    pub cron: Option<String>,
+    pub timezone: Option<String>,
+    pub delay: Option<i64>,
+    pub query_type: Option<QueryType>,
+    pub query: Option<String>,
+    pub database: Option<String>,
+    pub priority: Option<i64>,
+    pub retry_limit: Option<u64>,
+    pub result_url: Option<String>,
+}
+
+impl ScheduleParams {
+    pub fn new() -> ScheduleParams {
+        ScheduleParams::default()
+    }
+
+    pub fn cron(mut self, cron: &str) -> ScheduleParams {
+        self.cron = Some(cron.to_string());
+        self
+    }
+
+    pub fn timezone(mut self, timezone: &str) -> ScheduleParams {
+        self.timezone = Some(timezone.to_string());
+        self
+    }
+
+    pub fn delay(mut self, delay: i64) -> ScheduleParams {
+        self.delay = Some(delay);
+        self
+    }
+
+    pub fn query(mut self, query_type: QueryType, query: &str) -> ScheduleParams {
+        self.query_type = Some(query_type);
+        self.query = Some(query.to_string());
+        self
+    }
+
+    pub fn database(mut self, database: &str) -> ScheduleParams {
+        self.database = Some(database.to_string());
+        self
+    }
+
+    pub fn priority(mut self, priority: i64) -> ScheduleParams {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn retry_limit(mut self, retry_limit: u64) -> ScheduleParams {
+        self.retry_limit = Some(retry_limit);
+        self
+    }
+
+    pub fn result_url(mut self, result_url: &str) -> ScheduleParams {
+        self.result_url = Some(result_url.to_string());
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct ScheduledRun {
+    pub job_id: u64,
+    pub job_type: JobType,
+    pub scheduled_at: Option<TimeStamp>,
+}
+
+#[derive(Debug)]
+pub struct ScheduledJob {
+    pub scheduled_at: Option<TimeStamp>,
+    pub job: Job,
+}
+
+// Escapes everything except unreserved characters so that credentials and names
+// can be embedded into a result URL
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::new();
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn percent_encode_path(path: &str) -> String {
+    path.split('/')
+        .map(percent_encode)
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn result_url_query(params: &[(&str, &Option<String>)]) -> String {
+    let params = params
+        .iter()
+        .filter_map(|&(k, v)| v.as_ref().map(|v| format!("{}={}", k, percent_encode(v))))
+        .collect::<Vec<String>>();
+    if params.is_empty() {
+        "".to_string()
+    } else {
+        format!("?{}", params.join("&"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResultMode {
+    Append,
+    Replace,
+    Truncate,
+    Update,
+}
+
+impl ToString for ResultMode {
+    fn to_string(&self) -> String {
+        match self {
+            &ResultMode::Append => "append".to_string(),
+            &ResultMode::Replace => "replace".to_string(),
+            &ResultMode::Truncate => "truncate".to_string(),
+            &ResultMode::Update => "update".to_string(),
+        }
+    }
+}
+
+// Another table in Treasure Data
+#[derive(Debug, Clone)]
+pub struct TdResultTarget {
+    pub database: String,
+    pub table: String,
+    pub mode: Option<ResultMode>,
+}
+
+impl TdResultTarget {
+    pub fn new(database: &str, table: &str) -> TdResultTarget {
+        TdResultTarget {
+            database: database.to_string(),
+            table: table.to_string(),
+            mode: None,
+        }
+    }
+
+    pub fn mode(mut self, mode: ResultMode) -> TdResultTarget {
+        self.mode = Some(mode);
+        self
+    }
+}
+
+// `endpoint` can point to S3 compatible storage other than AWS
+#[derive(Debug, Clone)]
+pub struct S3ResultTarget {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub endpoint: Option<String>,
+    pub bucket: String,
+    pub path: String,
+    pub format: Option<String>,
+    pub compression: Option<String>,
+}
+
+impl S3ResultTarget {
+    pub fn new(
+        access_key_id: &str,
+        secret_access_key: &str,
+        bucket: &str,
+        path: &str,
+    ) -> S3ResultTarget {
+        S3ResultTarget {
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            endpoint: None,
+            bucket: bucket.to_string(),
+            path: path.to_string(),
+            format: None,
+            compression: None,
+        }
+    }
+
+    pub fn endpoint(mut self, endpoint: &str) -> S3ResultTarget {
+        self.endpoint = Some(endpoint.to_string());
+        self
+    }
+
+    pub fn format(mut self, format: &str) -> S3ResultTarget {
+        self.format = Some(format.to_string());
+        self
+    }
+
+    pub fn compression(mut self, compression: &str) -> S3ResultTarget {
+        self.compression = Some(compression.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PostgreSqlResultTarget {
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+    pub table: String,
+    pub mode: Option<ResultMode>,
+}
+
+impl PostgreSqlResultTarget {
+    pub fn new(
+        host: &str,
+        user: &str,
+        password: &str,
+        database: &str,
+        table: &str,
+    ) -> PostgreSqlResultTarget {
+        PostgreSqlResultTarget {
+            host: host.to_string(),
+            port: None,
+            user: user.to_string(),
+            password: password.to_string(),
+            database: database.to_string(),
+            table: table.to_string(),
+            mode: None,
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> PostgreSqlResultTarget {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn mode(mut self, mode: ResultMode) -> PostgreSqlResultTarget {
+        self.mode = Some(mode);
+        self
+    }
+}
+
+// Where a query result is written. Use `Url` for targets which don't have a typed variant
+#[derive(Debug, Clone)]
+pub enum ResultTarget {
+    Td(TdResultTarget),
+    S3(S3ResultTarget),
+    PostgreSql(PostgreSqlResultTarget),
+    Url(String),
+}
+
+impl ResultTarget {
+    pub fn to_url(&self) -> String {
+        match self {
+            &ResultTarget::Td(ref x) => format!(
+                "td://@/{}/{}{}",
+                percent_encode(&x.database),
+                percent_encode(&x.table),
+                result_url_query(&[("mode", &x.mode.map(|m| m.to_string()))])
+            ),
+            &ResultTarget::S3(ref x) => format!(
+                "s3://{}:{}@{}/{}/{}{}",
+                percent_encode(&x.access_key_id),
+                percent_encode(&x.secret_access_key),
+                x.endpoint.as_ref().map(|e| e.as_str()).unwrap_or(""),
+                percent_encode(&x.bucket),
+                percent_encode_path(x.path.trim_start_matches('/')),
+                result_url_query(&[("format", &x.format), ("compression", &x.compression)])
+            ),
+            &ResultTarget::PostgreSql(ref x) => format!(
+                "postgresql://{}:{}@{}{}/{}/{}{}",
+                percent_encode(&x.user),
+                percent_encode(&x.password),
+                x.host,
+                x.port.map(|p| format!(":{}", p)).unwrap_or("".to_string()),
+                percent_encode(&x.database),
+                percent_encode(&x.table),
+                result_url_query(&[("mode", &x.mode.map(|m| m.to_string()))])
+            ),
+            &ResultTarget::Url(ref x) => x.clone(),
+        }
+    }
+}
+
+impl From<TdResultTarget> for ResultTarget {
+    fn from(target: TdResultTarget) -> ResultTarget {
+        ResultTarget::Td(target)
+    }
+}
+
+impl From<S3ResultTarget> for ResultTarget {
+    fn from(target: S3ResultTarget) -> ResultTarget {
+        ResultTarget::S3(target)
+    }
+}
+
+impl From<PostgreSqlResultTarget> for ResultTarget {
+    fn from(target: PostgreSqlResultTarget) -> ResultTarget {
+        ResultTarget::PostgreSql(target)
+    }
+}
+
+// A job which writes the result of `source_job_id` into a `ResultTarget`.
+// Track it with `Client::wait_job` and `Client::job` like other jobs
+#[derive(Debug)]
+pub struct ResultExportJob {
+    pub job_id: u64,
+    pub source_job_id: u64,
+}
+
+// Every format is gzipped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    JsonLines,
+    Tsv,
+}
+
+impl ToString for ExportFormat {
+    fn to_string(&self) -> String {
+        match self {
+            &ExportFormat::Json => "json.gz".to_string(),
+            &ExportFormat::JsonLines => "jsonl.gz".to_string(),
+            &ExportFormat::Tsv => "tsv.gz".to_string(),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = InvalidArgument;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json.gz" => Ok(ExportFormat::Json),
+            "jsonl.gz" => Ok(ExportFormat::JsonLines),
+            "tsv.gz" => Ok(ExportFormat::Tsv),
+            _ => Err(InvalidArgument {
+                key: "file_format".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+// Records whose `time` is in [from, to) are written under `s3://bucket/path_prefix`.
+// `endpoint` can point to S3 compatible storage other than AWS
+#[derive(Debug, Clone)]
+pub struct TableExportParams {
+    pub bucket: String,
+    pub path_prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub endpoint: Option<String>,
+    pub format: ExportFormat,
+    pub from: Option<TimeStamp>,
+    pub to: Option<TimeStamp>,
+}
+
+impl TableExportParams {
+    pub fn new(
+        bucket: &str,
+        path_prefix: &str,
+        access_key_id: &str,
+        secret_access_key: &str,
+    ) -> TableExportParams {
+        TableExportParams {
+            bucket: bucket.to_string(),
+            path_prefix: path_prefix.to_string(),
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.to_string(),
+            endpoint: None,
+            format: ExportFormat::JsonLines,
+            from: None,
+            to: None,
+        }
+    }
+
+    pub fn endpoint(mut self, endpoint: &str) -> TableExportParams {
+        self.endpoint = Some(endpoint.to_string());
+        self
+    }
+
+    pub fn format(mut self, format: ExportFormat) -> TableExportParams {
+        self.format = format;
+        self
+    }
+
+    pub fn from(mut self, from: TimeStamp) -> TableExportParams {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn to(mut self, to: TimeStamp) -> TableExportParams {
+        self.to = Some(to);
+        self
+    }
+}
+
+// Data Connector session. `config` is the connector configuration such as
+// `{"in": {"type": "s3", ...}, "out": {"mode": "append"}}`
+#[derive(Debug)]
+pub struct BulkLoadSession {
+    pub name: String,
+    pub cron: Option<String>,
+    pub timezone: String,
+    pub delay: i64,
+    pub time_column: Option<String>,
+    pub database: String,
+    pub table: String,
+    pub config: json::Json,
+}
+
+// Unset fields are left as they are on update
+#[derive(Debug, Clone, Default)]
+pub struct BulkLoadSessionParams {
+    pub cron: Option<String>,
+    pub timezone: Option<String>,
+    pub delay: Option<i64>,
+    pub time_column: Option<String>,
+    pub database: Option<String>,
+    pub table: Option<String>,
+    pub config: Option<json::Json>,
+}
+
+impl BulkLoadSessionParams {
+    pub fn new() -> BulkLoadSessionParams {
+        BulkLoadSessionParams::default()
+    }
+
+    pub fn cron(mut self, cron: &str) -> BulkLoadSessionParams {
+        self.cron = Some(cron.to_string());
+        self
+    }
+
+    pub fn timezone(mut self, timezone: &str) -> BulkLoadSessionParams {
+        self.timezone = Some(timezone.to_string());
+        self
+    }
+
+    pub fn delay(mut self, delay: i64) -> BulkLoadSessionParams {
+        self.delay = Some(delay);
+        self
+    }
+
+    pub fn time_column(mut self, time_column: &str) -> BulkLoadSessionParams {
+        self.time_column = Some(time_column.to_string());
+        self
+    }
+
+    pub fn target(mut self, database: &str, table: &str) -> BulkLoadSessionParams {
+        self.database = Some(database.to_string());
+        self.table = Some(table.to_string());
+        self
+    }
+
+    pub fn config(mut self, config: json::Json) -> BulkLoadSessionParams {
+        self.config = Some(config);
+        self
+    }
+}
+
+#[derive(Debug)]
+pub struct BulkLoadJob {
+    pub job_id: u64,
+    pub status: JobStatus,
+    pub num_records: Option<u64>,
+    pub created_at: Option<TimeStamp>,
+    pub start_at: Option<TimeStamp>,
+    pub end_at: Option<TimeStamp>,
+}
+
+#[derive(Debug)]
+pub struct BulkLoadPreview {
+    // Pairs of a column name and its type
+    pub schema: Vec<(String, String)>,
+    pub records: Vec<Vec<json::Json>>,
+}
+
+// Type of a column in a job result. Both Presto and Hive notations are accepted,
+// e.g. `array(varchar)` and `array<string>`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnType {
+    Boolean,
+    TinyInt,
+    SmallInt,
+    Int,
+    BigInt,
+    Real,
+    Double,
+    Decimal,
+    Varchar,
+    Varbinary,
+    Json,
+    Date,
+    Timestamp,
+    Array(Box<ColumnType>),
+    Map(Box<ColumnType>, Box<ColumnType>),
+    Row(Vec<ResultColumn>),
+    Unknown(String),
+}
+
+struct ColumnTypeParser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> ColumnTypeParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.pos..]
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.skip_spaces();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_spaces();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return None;
+        }
+        self.pos += len;
+        Some(&rest[..len])
+    }
+
+    // Skips parameters like `(10, 2)` of `decimal(10, 2)`
+    fn skip_params(&mut self) -> Option<()> {
+        self.skip_spaces();
+        if self.peek() == Some('(') {
+            let len = self.rest().find(')')?;
+            self.pos += len + 1;
+        }
+        Some(())
+    }
+
+    fn column_type(&mut self) -> Option<ColumnType> {
+        let name = self.ident()?.to_lowercase();
+        let column_type = match name.as_str() {
+            "boolean" => ColumnType::Boolean,
+            "tinyint" => ColumnType::TinyInt,
+            "smallint" => ColumnType::SmallInt,
+            "int" | "integer" => ColumnType::Int,
+            "bigint" | "long" => ColumnType::BigInt,
+            "real" | "float" => ColumnType::Real,
+            "double" => {
+                // `double precision`
+                let pos = self.pos;
+                if self.ident().map(|x| x.to_lowercase()) != Some("precision".to_string()) {
+                    self.pos = pos;
+                }
+                ColumnType::Double
+            }
+            "decimal" => {
+                self.skip_params()?;
+                ColumnType::Decimal
+            }
+            "varchar" | "char" | "string" => {
+                self.skip_params()?;
+                ColumnType::Varchar
+            }
+            "varbinary" | "binary" => ColumnType::Varbinary,
+            "json" => ColumnType::Json,
+            "date" => ColumnType::Date,
+            "timestamp" => {
+                self.skip_params()?;
+                // `timestamp with time zone`
+                let pos = self.pos;
+                if self.ident().map(|x| x.to_lowercase()) == Some("with".to_string()) {
+                    self.ident()?;
+                    self.ident()?;
+                } else {
+                    self.pos = pos;
+                }
+                ColumnType::Timestamp
+            }
+            "array" => {
+                let close = self.open()?;
+                let element_type = self.column_type()?;
+                self.expect(close)?;
+                ColumnType::Array(Box::new(element_type))
+            }
+            "map" => {
+                let close = self.open()?;
+                let key_type = self.column_type()?;
+                self.expect(',')?;
+                let value_type = self.column_type()?;
+                self.expect(close)?;
+                ColumnType::Map(Box::new(key_type), Box::new(value_type))
+            }
+            "row" | "struct" => {
+                let close = self.open()?;
+                let mut fields = vec![];
+                loop {
+                    fields.push(self.field()?);
+                    self.skip_spaces();
+                    if self.expect(',').is_none() {
+                        break;
+                    }
+                }
+                self.expect(close)?;
+                ColumnType::Row(fields)
+            }
+            _ => {
+                self.skip_params()?;
+                ColumnType::Unknown(name)
+            }
+        };
+        Some(column_type)
+    }
+
+    // Returns the closing character
+    fn open(&mut self) -> Option<char> {
+        if self.expect('<').is_some() {
+            Some('>')
+        } else if self.expect('(').is_some() {
+            Some(')')
+        } else {
+            None
+        }
+    }
+
+    // `name type` in Presto, `name:type` in Hive, or just `type`
+    fn field(&mut self) -> Option<ResultColumn> {
+        let pos = self.pos;
+        let name = self.ident()?;
+        self.skip_spaces();
+        match self.peek() {
+            Some(':') => {
+                self.pos += 1;
+            }
+            Some(c) if c.is_alphanumeric() => (),
+            _ => {
+                self.pos = pos;
+                return Some(ResultColumn {
+                    name: "".to_string(),
+                    column_type: self.column_type()?,
+                });
+            }
+        }
+        Some(ResultColumn {
+            name: name.to_string(),
+            column_type: self.column_type()?,
+        })
+    }
+}
+
+impl<'a> From<&'a str> for ColumnType {
+    fn from(s: &'a str) -> Self {
+        let mut parser = ColumnTypeParser { s: s, pos: 0 };
+        match parser.column_type() {
+            Some(column_type) if parser.rest().trim().is_empty() => column_type,
+            _ => ColumnType::Unknown(s.to_string()),
+        }
+    }
+}
+
+impl ToString for ColumnType {
+    fn to_string(&self) -> String {
+        match self {
+            &ColumnType::Boolean => "boolean".to_string(),
+            &ColumnType::TinyInt => "tinyint".to_string(),
+            &ColumnType::SmallInt => "smallint".to_string(),
+            &ColumnType::Int => "integer".to_string(),
+            &ColumnType::BigInt => "bigint".to_string(),
+            &ColumnType::Real => "real".to_string(),
+            &ColumnType::Double => "double".to_string(),
+            &ColumnType::Decimal => "decimal".to_string(),
+            &ColumnType::Varchar => "varchar".to_string(),
+            &ColumnType::Varbinary => "varbinary".to_string(),
+            &ColumnType::Json => "json".to_string(),
+            &ColumnType::Date => "date".to_string(),
+            &ColumnType::Timestamp => "timestamp".to_string(),
+            &ColumnType::Array(ref x) => format!("array({})", x.to_string()),
+            &ColumnType::Map(ref k, ref v) => format!("map({}, {})", k.to_string(), v.to_string()),
+            &ColumnType::Row(ref fields) => format!(
+                "row({})",
+                fields
+                    .iter()
+                    .map(|field| if field.name.is_empty() {
+                        field.column_type.to_string()
+                    } else {
+                        format!("{} {}", field.name, field.column_type.to_string())
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            &ColumnType::Unknown(ref s) => s.clone(),
+        }
+    }
+}
+
+fn integer_value(value: &Value) -> Option<i64> {
+    match *value {
+        Value::Integer(Integer::I64(x)) => Some(x),
+        Value::Integer(Integer::U64(x)) if x <= i64::max_value() as u64 => Some(x as i64),
+        Value::Float(Float::F32(x)) => float_to_i64(x as f64),
+        Value::Float(Float::F64(x)) => float_to_i64(x),
+        Value::String(ref x) => x.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// Only integral floats within the range of i64 are converted. `i64::max_value() as f64`
+// is rounded up to 2^63, which is out of the range
+fn float_to_i64(x: f64) -> Option<i64> {
+    if x.fract() == 0.0 && x >= i64::min_value() as f64 && x < -(i64::min_value() as f64) {
+        Some(x as i64)
+    } else {
+        None
+    }
+}
+
+fn integer_in_range(value: Value, min: i64, max: i64) -> Result<Value, Value> {
+    match integer_value(&value) {
+        Some(x) if min <= x && x <= max => Ok(Value::Integer(Integer::I64(x))),
+        _ => Err(value),
+    }
+}
+
+fn float_value(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Integer(Integer::I64(x)) => Some(x as f64),
+        Value::Integer(Integer::U64(x)) => Some(x as f64),
+        Value::Float(Float::F32(x)) => Some(x as f64),
+        Value::Float(Float::F64(x)) => Some(x),
+        Value::String(ref x) => x.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// Hive returns complex types as JSON strings
+fn complex_value(value: Value) -> Value {
+    match value {
+        Value::String(s) => match json::Json::from_str(&s) {
+            Ok(json) => Value::from(json),
+            Err(_) => Value::String(s),
+        },
+        value => value,
+    }
+}
+
+impl ColumnType {
+    // Converts a value read from a job result into this type. `Nil` stays `Nil`
+    pub fn convert(&self, value: Value) -> Result<Value, TreasureDataError> {
+        // A value which can't be converted is returned as `Err` for the error message
+        let converted = match (self, value) {
+            (_, Value::Nil) => Ok(Value::Nil),
+            (&ColumnType::Unknown(_), value) => Ok(value),
+            (&ColumnType::Boolean, Value::Boolean(x)) => Ok(Value::Boolean(x)),
+            (&ColumnType::Boolean, value) => match value {
+                Value::String(ref x) if x == "true" => Ok(Value::Boolean(true)),
+                Value::String(ref x) if x == "false" => Ok(Value::Boolean(false)),
+                value => integer_value(&value)
+                    .map(|x| Value::Boolean(x != 0))
+                    .ok_or(value),
+            },
+            (&ColumnType::TinyInt, value) => {
+                integer_in_range(value, i8::min_value() as i64, i8::max_value() as i64)
+            }
+            (&ColumnType::SmallInt, value) => {
+                integer_in_range(value, i16::min_value() as i64, i16::max_value() as i64)
+            }
+            (&ColumnType::Int, value) => {
+                integer_in_range(value, i32::min_value() as i64, i32::max_value() as i64)
+            }
+            (&ColumnType::BigInt, value) => {
+                integer_in_range(value, i64::min_value(), i64::max_value())
+            }
+            (&ColumnType::Real, value) => float_value(&value)
+                .map(|x| Value::Float(Float::F32(x as f32)))
+                .ok_or(value),
+            (&ColumnType::Double, value) => float_value(&value)
+                .map(|x| Value::Float(Float::F64(x)))
+                .ok_or(value),
+            (&ColumnType::Varbinary, Value::String(x)) => Ok(Value::Binary(x.into_bytes())),
+            (&ColumnType::Varbinary, Value::Binary(x)) => Ok(Value::Binary(x)),
+            (&ColumnType::Varbinary, value) => Err(value),
+            (&ColumnType::Array(ref element_type), value) => match complex_value(value) {
+                Value::Array(xs) => Ok(Value::Array(
+                    xs.into_iter()
+                        .map(|x| element_type.convert(x))
+                        .collect::<Result<Vec<Value>, TreasureDataError>>()?,
+                )),
+                value => Err(value),
+            },
+            (&ColumnType::Map(ref key_type, ref value_type), value) => match complex_value(value) {
+                Value::Map(kvs) => Ok(Value::Map(
+                    kvs.into_iter()
+                        .map(|(k, v)| Ok((key_type.convert(k)?, value_type.convert(v)?)))
+                        .collect::<Result<Vec<(Value, Value)>, TreasureDataError>>()?,
+                )),
+                value => Err(value),
+            },
+            // Rows are represented as arrays of their fields
+            (&ColumnType::Row(ref fields), value) => match complex_value(value) {
+                Value::Array(ref xs) if xs.len() == fields.len() => Ok(Value::Array(
+                    xs.iter()
+                        .zip(fields.iter())
+                        .map(|(x, field)| field.column_type.convert(x.clone()))
+                        .collect::<Result<Vec<Value>, TreasureDataError>>()?,
+                )),
+                Value::Map(ref kvs) => Ok(Value::Array(
+                    fields
+                        .iter()
+                        .map(|field| {
+                            let item = kvs
+                                .iter()
+                                .find(|&&(ref k, _)| match *k {
+                                    Value::String(ref k) => *k == field.name,
+                                    _ => false,
+                                })
+                                .map(|&(_, ref v)| v.clone())
+                                .unwrap_or(Value::Nil);
+                            field.column_type.convert(item)
+                        })
+                        .collect::<Result<Vec<Value>, TreasureDataError>>()?,
+                )),
+                value => Err(value),
+            },
+            // Others are textual
+            (_, Value::String(x)) => Ok(Value::String(x)),
+            (_, Value::Boolean(x)) => Ok(Value::String(x.to_string())),
+            (_, Value::Integer(Integer::I64(x))) => Ok(Value::String(x.to_string())),
+            (_, Value::Integer(Integer::U64(x))) => Ok(Value::String(x.to_string())),
+            (_, Value::Float(Float::F32(x))) => Ok(Value::String(x.to_string())),
+            (_, Value::Float(Float::F64(x))) => Ok(Value::String(x.to_string())),
+            (_, Value::Binary(x)) => String::from_utf8(x)
+                .map(Value::String)
+                .map_err(|err| Value::Binary(err.into_bytes())),
+            (_, value) => Err(value),
+        };
+        converted.map_err(|value| {
+            TreasureDataError::InvalidArgumentError(InvalidArgument {
+                key: self.to_string(),
+                value: format!("{:?}", value),
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultColumn {
+    pub name: String,
+    pub column_type: ColumnType,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ResultSchema {
+    pub columns: Vec<ResultColumn>,
+}
+
+impl ResultSchema {
+    // Takes `Job::hive_result_schema` such as `[["cnt", "bigint"]]`
+    pub fn from_hive_result_schema(schema: &Vec<Vec<String>>) -> ResultSchema {
+        ResultSchema {
+            columns: schema
+                .iter()
+                .map(|column| ResultColumn {
+                    name: column.get(0).cloned().unwrap_or_default(),
+                    column_type: column
+                        .get(1)
+                        .map(|x| ColumnType::from(x.as_str()))
+                        .unwrap_or(ColumnType::Unknown("".to_string())),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+
+    // Converts each value into the type of its column. An empty schema, which some job
+    // types have, keeps the row as is
+    pub fn convert_row(&self, row: Vec<Value>) -> Result<Vec<Value>, TreasureDataError> {
+        if self.columns.is_empty() {
+            return Ok(row);
+        }
+        if row.len() != self.columns.len() {
+            return Err(TreasureDataError::InvalidArgumentError(InvalidArgument {
+                key: "row".to_string(),
+                value: format!("{} values for {} columns", row.len(), self.columns.len()),
+            }));
+        }
+        row.into_iter()
+            .zip(self.columns.iter())
+            .map(|(value, column)| column.column_type.convert(value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error::TreasureDataError;
+    use model::*;
+
+    #[test]
+    fn result_schema() {
+        assert_eq!(ColumnType::BigInt, ColumnType::from("bigint"));
+        assert_eq!(ColumnType::Varchar, ColumnType::from("varchar(10)"));
+        assert_eq!(ColumnType::Decimal, ColumnType::from("decimal(10, 2)"));
+        assert_eq!(
+            ColumnType::Timestamp,
+            ColumnType::from("timestamp with time zone")
+        );
+        assert_eq!(
+            ColumnType::Array(Box::new(ColumnType::Varchar)),
+            ColumnType::from("array<string>")
+        );
+        assert_eq!(
+            ColumnType::Map(Box::new(ColumnType::Varchar), Box::new(ColumnType::Double)),
+            ColumnType::from("map(varchar, double)")
+        );
+        let row = ColumnType::Row(vec![
+            ResultColumn {
+                name: "a".to_string(),
+                column_type: ColumnType::Int,
+            },
+            ResultColumn {
+                name: "b".to_string(),
+                column_type: ColumnType::Array(Box::new(ColumnType::Boolean)),
+            },
+        ]);
+        assert_eq!(row, ColumnType::from("row(a integer, b array(boolean))"));
+        assert_eq!(row, ColumnType::from("struct<a:int,b:array<boolean>>"));
+        assert_eq!(
+            "row(a integer, b array(boolean))",
+            ColumnType::from("struct<a:int,b:array<boolean>>").to_string()
+        );
+        assert_eq!(
+            ColumnType::Unknown("array<".to_string()),
+            ColumnType::from("array<")
+        );
+
+        let schema = ResultSchema::from_hive_result_schema(&vec![
+            vec!["cnt".to_string(), "bigint".to_string()],
+            vec!["avg".to_string(), "double".to_string()],
+            vec!["tags".to_string(), "array<string>".to_string()],
+            vec!["name".to_string(), "varchar".to_string()],
+        ]);
+        assert_eq!(Some(2), schema.column_index("tags"));
+        assert_eq!(
+            vec![
+                Value::Integer(Integer::I64(42)),
+                Value::Float(Float::F64(1.5)),
+                Value::Array(vec![
+                    Value::String("x".to_string()),
+                    Value::String("y".to_string()),
+                ]),
+                Value::String("7".to_string()),
+            ],
+            schema
+                .convert_row(vec![
+                    Value::String("42".to_string()),
+                    Value::String("1.5".to_string()),
+                    Value::String(r#"["x","y"]"#.to_string()),
+                    Value::Integer(Integer::U64(7)),
+                ])
+                .unwrap()
+        );
+        assert_eq!(
+            vec![Value::Nil; 4],
+            schema.convert_row(vec![Value::Nil; 4]).unwrap()
+        );
+        match schema.convert_row(vec![Value::Nil]) {
+            Err(TreasureDataError::InvalidArgumentError(ref x)) => {
+                assert_eq!("1 values for 4 columns", x.value)
+            }
+            x => panic!("Unexpected result: {:?}", x),
+        }
+        match schema.convert_row(vec![
+            Value::String("x".to_string()),
+            Value::Nil,
+            Value::Nil,
+            Value::Nil,
+        ]) {
+            Err(TreasureDataError::InvalidArgumentError(ref x)) => {
+                assert_eq!("bigint", x.key);
+                assert_eq!(r#"String("x")"#, x.value);
+            }
+            x => panic!("Unexpected result: {:?}", x),
+        }
+
+        // An empty schema keeps rows as they are
+        assert_eq!(
+            vec![Value::String("x".to_string())],
+            ResultSchema::default()
+                .convert_row(vec![Value::String("x".to_string())])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn convert_to_integer() {
+        assert_eq!(
+            Value::Integer(Integer::I64(3)),
+            ColumnType::BigInt
+                .convert(Value::Float(Float::F64(3.0)))
+                .unwrap()
+        );
+        assert_eq!(
+            Value::Integer(Integer::I64(-128)),
+            ColumnType::TinyInt
+                .convert(Value::String("-128".to_string()))
+                .unwrap()
+        );
+        for &(ref column_type, ref value) in [
+            (ColumnType::BigInt, Value::Float(Float::F64(1.5))),
+            (ColumnType::BigInt, Value::Float(Float::F64(9.3e18))),
+            (
+                ColumnType::BigInt,
+                Value::Float(Float::F64(::std::f64::NAN)),
+            ),
+            (
+                ColumnType::BigInt,
+                Value::Integer(Integer::U64(::std::u64::MAX)),
+            ),
+            (ColumnType::TinyInt, Value::Integer(Integer::I64(128))),
+            (ColumnType::SmallInt, Value::Integer(Integer::I64(-32769))),
+            (ColumnType::Int, Value::String("2147483648".to_string())),
+        ]
+        .iter()
+        {
+            assert!(
+                column_type.convert(value.clone()).is_err(),
+                "{:?} {:?}",
+                column_type,
+                value
+            );
+        }
+    }
+}
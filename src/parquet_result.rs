@@ -0,0 +1,292 @@
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use arrow_result::*;
+use client::*;
+use error::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParquetCompression {
+    Uncompressed,
+    Snappy,
+    Gzip,
+    Zstd,
+}
+
+impl ToString for ParquetCompression {
+    fn to_string(&self) -> String {
+        match self {
+            &ParquetCompression::Uncompressed => "uncompressed".to_string(),
+            &ParquetCompression::Snappy => "snappy".to_string(),
+            &ParquetCompression::Gzip => "gzip".to_string(),
+            &ParquetCompression::Zstd => "zstd".to_string(),
+        }
+    }
+}
+
+impl FromStr for ParquetCompression {
+    type Err = InvalidArgument;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uncompressed" => Ok(ParquetCompression::Uncompressed),
+            "snappy" => Ok(ParquetCompression::Snappy),
+            "gzip" => Ok(ParquetCompression::Gzip),
+            "zstd" => Ok(ParquetCompression::Zstd),
+            _ => Err(InvalidArgument {
+                key: "compression".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl ParquetCompression {
+    fn to_parquet(&self) -> Compression {
+        match self {
+            &ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+            &ParquetCompression::Snappy => Compression::SNAPPY,
+            &ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+            &ParquetCompression::Zstd => Compression::ZSTD(ZstdLevel::default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    pub row_group_size: usize,
+    pub compression: ParquetCompression,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> ParquetOptions {
+        ParquetOptions {
+            row_group_size: 100000,
+            compression: ParquetCompression::Snappy,
+        }
+    }
+}
+
+impl ParquetOptions {
+    pub fn new() -> ParquetOptions {
+        ParquetOptions::default()
+    }
+
+    // Maximum number of rows in a row group
+    pub fn row_group_size(mut self, row_group_size: usize) -> ParquetOptions {
+        self.row_group_size = row_group_size;
+        self
+    }
+
+    pub fn compression(mut self, compression: ParquetCompression) -> ParquetOptions {
+        self.compression = compression;
+        self
+    }
+}
+
+// `WriterProperties` panics on a row group size of 0
+fn check_options(options: &ParquetOptions) -> Result<(), TreasureDataError> {
+    if options.row_group_size == 0 {
+        return Err(TreasureDataError::InvalidArgumentError(InvalidArgument {
+            key: "row_group_size".to_string(),
+            value: options.row_group_size.to_string(),
+        }));
+    }
+    Ok(())
+}
+
+// Writes record batches into `out_file` and returns the number of written rows
+pub fn write_parquet(
+    batches: RecordBatches,
+    out_file: File,
+    options: &ParquetOptions,
+) -> Result<u64, TreasureDataError> {
+    check_options(options)?;
+    let props = WriterProperties::builder()
+        .set_max_row_group_size(options.row_group_size)
+        .set_compression(options.compression.to_parquet())
+        .build();
+    let mut writer = ArrowWriter::try_new(out_file, batches.schema(), Some(props))?;
+    let mut num_rows = 0;
+    for batch in batches {
+        let batch = batch?;
+        num_rows += batch.num_rows() as u64;
+        writer.write(&batch)?;
+    }
+    writer.close()?;
+    Ok(num_rows)
+}
+
+impl<R> Client<R>
+where
+    R: RequestExecutor,
+{
+    // Streams the result of a finished job into a Parquet file at `path`. Columns
+    // are typed from the result schema of the job. The file is written to
+    // `<path>.part` first and renamed to `path` only when it's complete
+    pub fn download_job_result_parquet(
+        &self,
+        job_id: u64,
+        path: &Path,
+        options: &ParquetOptions,
+    ) -> Result<u64, TreasureDataError> {
+        check_options(options)?;
+        let mut part_path = path.as_os_str().to_owned();
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+
+        let result = self
+            .job_result_arrow(job_id, options.row_group_size)
+            .and_then(|batches| write_parquet(batches, File::create(&part_path)?, options));
+        match result {
+            Ok(num_rows) => {
+                fs::rename(&part_path, path)?;
+                Ok(num_rows)
+            }
+            Err(err) => {
+                if part_path.exists() {
+                    fs::remove_file(&part_path)?;
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mockito;
+    use self::mockito::mock;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use rmpv::Value as RmpvValue;
+    use std::fs::File;
+    use std::io::prelude::*;
+    use tempdir::TempDir;
+
+    use client::Client;
+    use parquet_result::*;
+
+    #[test]
+    fn download_job_result_parquet() {
+        let _mock_job = mock("GET", "/v3/job/show/12345")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                r#"{"job_id":"12345", "type":"hive", "query":"select 1", "status":"success",
+                    "url":"https://console.treasuredata.com/jobs/12345",
+                    "created_at":"2016-01-01 00:00:00 UTC", "start_at":"2016-01-01 00:00:01 UTC",
+                    "end_at":"2016-01-01 00:00:10 UTC", "cpu_time":null, "result_size":20,
+                    "hive_result_schema":"[[\"code\",\"int\"],[\"attrs\",\"map<string,double>\"]]",
+                    "priority":0, "retry_limit":0, "duration":9, "num_records":3}"#,
+            )
+            .create();
+
+        let mut msgpack = vec![];
+        for code in vec![200, 404, 500] {
+            let row =
+                RmpvValue::Array(vec![RmpvValue::from(code), RmpvValue::from(r#"{"x":1.5}"#)]);
+            ::rmpv::encode::write_value(&mut msgpack, &row).unwrap();
+        }
+        let mut e = GzEncoder::new(vec![], Compression::default());
+        e.write_all(&msgpack).unwrap();
+        let _mock_result = mock("GET", "/v3/job/result/12345?format=msgpack_gz")
+            .with_status(200)
+            .with_body(e.finish().unwrap())
+            .create();
+
+        let mut client = Client::new("1234abcd");
+        client.endpoint(&mockito::server_url());
+
+        let dir = TempDir::new("td-client-parquet").unwrap();
+        let path = dir.path().join("result.parquet");
+        let num_rows = client
+            .download_job_result_parquet(
+                12345,
+                &path,
+                &ParquetOptions::new()
+                    .row_group_size(2)
+                    .compression(ParquetCompression::from_str("zstd").unwrap()),
+            )
+            .unwrap();
+        assert_eq!(3, num_rows);
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(2, builder.metadata().num_row_groups());
+        assert_eq!(
+            vec!["code", "attrs"],
+            builder
+                .schema()
+                .fields()
+                .iter()
+                .map(|x| x.name().as_str())
+                .collect::<Vec<&str>>()
+        );
+        let num_read_rows: usize = builder
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum();
+        assert_eq!(3, num_read_rows);
+    }
+
+    #[test]
+    fn download_job_result_parquet_error() {
+        let _mock_job = mock("GET", "/v3/job/show/12346")
+            .with_status(200)
+            .with_header("Content-Type", "application/json")
+            .with_body(
+                r#"{"job_id":"12346", "type":"hive", "query":"select 1", "status":"success",
+                    "url":"https://console.treasuredata.com/jobs/12346",
+                    "created_at":"2016-01-01 00:00:00 UTC", "start_at":"2016-01-01 00:00:01 UTC",
+                    "end_at":"2016-01-01 00:00:10 UTC", "cpu_time":null, "result_size":20,
+                    "hive_result_schema":"[[\"code\",\"int\"]]",
+                    "priority":0, "retry_limit":0, "duration":9, "num_records":3}"#,
+            )
+            .create();
+
+        // The last row isn't an array
+        let mut msgpack = vec![];
+        for row in vec![
+            RmpvValue::Array(vec![RmpvValue::from(200)]),
+            RmpvValue::Array(vec![RmpvValue::from(404)]),
+            RmpvValue::from("broken"),
+        ] {
+            ::rmpv::encode::write_value(&mut msgpack, &row).unwrap();
+        }
+        let mut e = GzEncoder::new(vec![], Compression::default());
+        e.write_all(&msgpack).unwrap();
+        let _mock_result = mock("GET", "/v3/job/result/12346?format=msgpack_gz")
+            .with_status(200)
+            .with_body(e.finish().unwrap())
+            .create();
+
+        let mut client = Client::new("1234abcd");
+        client.endpoint(&mockito::server_url());
+
+        let dir = TempDir::new("td-client-parquet").unwrap();
+        let path = dir.path().join("result.parquet");
+        match client.download_job_result_parquet(
+            12346,
+            &path,
+            &ParquetOptions::new().row_group_size(0),
+        ) {
+            Err(TreasureDataError::InvalidArgumentError(x)) => {
+                assert_eq!("row_group_size", x.key)
+            }
+            x => panic!("Unexpected result: {:?}", x),
+        }
+        assert!(client
+            .download_job_result_parquet(12346, &path, &ParquetOptions::new().row_group_size(1))
+            .is_err());
+        assert!(!path.exists());
+        assert!(!dir.path().join("result.parquet.part").exists());
+    }
+}
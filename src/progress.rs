@@ -0,0 +1,60 @@
+use std::io;
+use std::io::prelude::*;
+
+// Amount of data transferred so far by an import, a download or an iteration over a job result
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TransferProgress {
+    pub bytes: u64,
+    pub total_bytes: Option<u64>,
+    // Rows aren't counted while raw msgpack.gz content is transferred
+    pub rows: Option<u64>,
+    pub total_rows: Option<u64>,
+}
+
+pub trait Progress {
+    fn update(&self, progress: &TransferProgress);
+}
+
+impl<F> Progress for F
+where
+    F: Fn(&TransferProgress),
+{
+    fn update(&self, progress: &TransferProgress) {
+        self(progress)
+    }
+}
+
+// Reports the number of bytes read through it to `progress`
+pub struct ProgressReader<R: Read, P: Progress> {
+    read: R,
+    progress: P,
+    state: TransferProgress,
+}
+
+impl<R: Read, P: Progress> ProgressReader<R, P> {
+    pub fn new(read: R, total_bytes: Option<u64>, progress: P) -> ProgressReader<R, P> {
+        ProgressReader {
+            read: read,
+            progress: progress,
+            state: TransferProgress {
+                total_bytes: total_bytes,
+                ..TransferProgress::default()
+            },
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.state.bytes
+    }
+}
+
+impl<R: Read, P: Progress> Read for ProgressReader<R, P> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.read.read(buf)?;
+        if len > 0 {
+            self.state.bytes += len as u64;
+            self.progress.update(&self.state);
+        }
+        Ok(len)
+    }
+}
@@ -7,6 +7,8 @@ use std::fs::File;
 use std::io;
 use tempdir::TempDir;
 
+use value::Value;
+
 pub struct TableImportWritableChunk {
     elms_in_row: Option<(u32, u32)>,
     file_path: String,
@@ -317,6 +319,18 @@ impl TableImportWritableChunk {
         Ok(())
     }
 
+    // Writes a whole value, e.g. a column of a job result row, as one element
+    pub fn write_key_and_value(
+        &mut self,
+        key: &str,
+        val: &Value,
+    ) -> Result<(), TableImportChunkError> {
+        write_str(&mut self.write, key)?;
+        val.write_msgpack(&mut self.write)?;
+        self.incr_elms_in_row()?;
+        Ok(())
+    }
+
     pub fn close(self) -> Result<TableImportReadableChunk, TableImportChunkError> {
         self.check_elm_number()?;
         self.write.finish()?;
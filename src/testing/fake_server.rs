@@ -0,0 +1,2401 @@
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rustc_serialize::json::{Json, ToJson};
+use std::collections::BTreeMap;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use client::{Client, DefaultRequestExecutor};
+use model::*;
+use value::*;
+
+// Result which the fake server returns for a job issued through `/v3/job/issue`
+#[derive(Clone, Debug)]
+pub struct FakeJobResult {
+    pub status: JobStatus,
+    pub result_schema: Vec<(String, String)>,
+    pub rows: Vec<Vec<Value>>,
+    pub running_polls: u32,
+    pub stderr: String,
+}
+
+impl FakeJobResult {
+    pub fn success(result_schema: &[(&str, &str)], rows: Vec<Vec<Value>>) -> FakeJobResult {
+        FakeJobResult {
+            status: JobStatus::Success,
+            result_schema: result_schema
+                .iter()
+                .map(|&(name, column_type)| (name.to_string(), column_type.to_string()))
+                .collect(),
+            rows: rows,
+            running_polls: 0,
+            stderr: "".to_string(),
+        }
+    }
+
+    // `message` is reported as the job's stderr
+    pub fn error(message: &str) -> FakeJobResult {
+        FakeJobResult {
+            status: JobStatus::Error,
+            result_schema: vec![],
+            rows: vec![],
+            running_polls: 0,
+            stderr: message.to_string(),
+        }
+    }
+
+    // Number of status checks answered with `running` before the job finishes
+    pub fn running_polls(mut self, running_polls: u32) -> FakeJobResult {
+        self.running_polls = running_polls;
+        self
+    }
+}
+
+// Object written by `/v3/export/run`. The fake server keeps these in memory
+// instead of uploading them to S3
+#[derive(Clone, Debug)]
+pub struct FakeExportedObject {
+    pub endpoint: Option<String>,
+    pub bucket: String,
+    pub key: String,
+    // Gzipped content as it would be stored in the bucket
+    pub body: Vec<u8>,
+}
+
+struct FakeTable {
+    schema: Vec<(String, String)>,
+    rows: Vec<Value>,
+    unique_ids: Vec<String>,
+    created_at: String,
+    updated_at: String,
+    last_import: Option<String>,
+    expire_days: Option<u32>,
+    include_v: bool,
+}
+
+struct FakeDatabase {
+    tables: BTreeMap<String, FakeTable>,
+    description: Option<String>,
+    access: BTreeMap<String, String>,
+    created_at: String,
+    updated_at: String,
+}
+
+struct FakeUser {
+    id: u64,
+    email: String,
+    api_keys: Vec<String>,
+    created_at: String,
+}
+
+struct FakeJob {
+    job_type: String,
+    database: String,
+    query: String,
+    result: FakeJobResult,
+    polls: u32,
+    killed: bool,
+    created_at: String,
+}
+
+impl FakeJob {
+    fn status(&self) -> JobStatus {
+        if self.killed {
+            JobStatus::Killed
+        } else if self.polls <= self.result.running_polls {
+            JobStatus::Running
+        } else {
+            self.result.status.clone()
+        }
+    }
+}
+
+struct FakeState {
+    databases: BTreeMap<String, FakeDatabase>,
+    jobs: BTreeMap<u64, FakeJob>,
+    next_job_id: u64,
+    query_results: Vec<(String, FakeJobResult)>,
+    default_result: FakeJobResult,
+    exported_objects: Vec<FakeExportedObject>,
+    users: BTreeMap<String, FakeUser>,
+    next_user_id: u64,
+    // Remaining count of job result responses to cut and the number of bytes sent before that
+    job_result_interruptions: (usize, usize),
+}
+
+// Stateful in-process fake of the Treasure Data API listening on a local port.
+// The server is stopped when it's dropped
+pub struct FakeServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<FakeState>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl FakeServer {
+    pub fn start() -> io::Result<FakeServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(FakeState {
+            databases: BTreeMap::new(),
+            jobs: BTreeMap::new(),
+            next_job_id: 1,
+            query_results: vec![],
+            default_result: FakeJobResult::success(&[], vec![]),
+            exported_objects: vec![],
+            users: BTreeMap::new(),
+            next_user_id: 1,
+            job_result_interruptions: (0, 0),
+        }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let state = state.clone();
+                            thread::spawn(move || {
+                                if let Err(err) = handle_connection(stream, &state) {
+                                    warn!("Fake server failed to handle a request: {}", err);
+                                }
+                            });
+                        }
+                        Err(err) => warn!("Fake server failed to accept a connection: {}", err),
+                    }
+                }
+            })
+        };
+
+        Ok(FakeServer {
+            addr: addr,
+            state: state,
+            shutdown: shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    // Returns a `Client` whose endpoints point to this server
+    pub fn client(&self, apikey: &str) -> Client<DefaultRequestExecutor> {
+        let mut client = Client::new(apikey);
+        client.endpoint(self.url().as_str());
+        client.import_endpoint(self.url().as_str());
+        client
+    }
+
+    pub fn create_database(&self, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .databases
+            .entry(name.to_string())
+            .or_insert_with(new_database);
+    }
+
+    pub fn create_table(&self, database_name: &str, name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .databases
+            .entry(database_name.to_string())
+            .or_insert_with(new_database)
+            .tables
+            .entry(name.to_string())
+            .or_insert_with(new_table);
+    }
+
+    // Returns records imported to the table, or `None` if the table doesn't exist
+    pub fn table_rows(&self, database_name: &str, name: &str) -> Option<Vec<Value>> {
+        let state = self.state.lock().unwrap();
+        state
+            .databases
+            .get(database_name)
+            .and_then(|db| db.tables.get(name))
+            .map(|table| table.rows.clone())
+    }
+
+    // Registers the result of jobs whose query contains `query_fragment`.
+    // Results registered earlier take precedence
+    pub fn on_query(&self, query_fragment: &str, result: FakeJobResult) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .query_results
+            .push((query_fragment.to_string(), result));
+    }
+
+    // Sets the result of jobs which don't match any query registered by `on_query`
+    pub fn default_job_result(&self, result: FakeJobResult) {
+        let mut state = self.state.lock().unwrap();
+        state.default_result = result;
+    }
+
+    // Returns queries issued so far in the order of job IDs
+    pub fn issued_queries(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state.jobs.values().map(|job| job.query.clone()).collect()
+    }
+
+    // Makes the next `times` job result downloads close the connection after
+    // `after_bytes` bytes of the body are sent
+    pub fn interrupt_job_results(&self, times: usize, after_bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.job_result_interruptions = (times, after_bytes);
+    }
+
+    // Returns objects written by table exports so far
+    pub fn exported_objects(&self) -> Vec<FakeExportedObject> {
+        let state = self.state.lock().unwrap();
+        state.exported_objects.clone()
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the accept loop so that it can notice the shutdown flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct FakeRequest {
+    method: String,
+    path: Vec<String>,
+    params: BTreeMap<String, String>,
+    headers: BTreeMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct FakeResponse {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+fn now() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+fn new_database() -> FakeDatabase {
+    FakeDatabase {
+        tables: BTreeMap::new(),
+        description: None,
+        access: BTreeMap::new(),
+        created_at: now(),
+        updated_at: now(),
+    }
+}
+
+fn new_table() -> FakeTable {
+    FakeTable {
+        schema: vec![],
+        rows: vec![],
+        unique_ids: vec![],
+        created_at: now(),
+        updated_at: now(),
+        last_import: None,
+        expire_days: None,
+        include_v: true,
+    }
+}
+
+// '+' is decoded as a space only in query parameters
+fn percent_decode(s: &str, plus_as_space: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = ::std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => {
+                        decoded.push(b);
+                        i += 3;
+                        continue;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn read_chunked_body<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let mut size_line = String::new();
+        reader.read_line(&mut size_line)?;
+        let size_str = size_line.trim().split(';').next().unwrap_or("");
+        let size = usize::from_str_radix(size_str, 16)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Invalid chunk size"))?;
+        if size == 0 {
+            // Skip trailers
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                if line.trim().is_empty() {
+                    return Ok(body);
+                }
+            }
+        }
+        let mut chunk = vec![0; size];
+        reader.read_exact(&mut chunk)?;
+        body.extend_from_slice(&chunk);
+        let mut crlf = String::new();
+        reader.read_line(&mut crlf)?;
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<FakeRequest> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().unwrap_or("").to_string();
+
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(pos) = line.find(':') {
+            headers.insert(
+                line[..pos].trim().to_lowercase(),
+                line[pos + 1..].trim().to_string(),
+            );
+        }
+    }
+
+    let body = if headers
+        .get("transfer-encoding")
+        .map_or(false, |x| x.eq_ignore_ascii_case("chunked"))
+    {
+        read_chunked_body(&mut reader)?
+    } else {
+        let content_length = headers
+            .get("content-length")
+            .and_then(|x| x.parse::<usize>().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        body
+    };
+
+    let (path, query) = match target.find('?') {
+        Some(pos) => (&target[..pos], &target[pos + 1..]),
+        None => (target.as_str(), ""),
+    };
+    let path = path
+        .split('/')
+        .filter(|x| !x.is_empty())
+        .map(|x| percent_decode(x, false))
+        .collect();
+    let params = query
+        .split('&')
+        .filter(|x| !x.is_empty())
+        .map(|kv| match kv.find('=') {
+            Some(pos) => (
+                percent_decode(&kv[..pos], true),
+                percent_decode(&kv[pos + 1..], true),
+            ),
+            None => (percent_decode(kv, true), "".to_string()),
+        })
+        .collect();
+
+    Ok(FakeRequest {
+        method: method,
+        path: path,
+        params: params,
+        headers: headers,
+        body: body,
+    })
+}
+
+// Start offset of `Range: bytes=N-`
+fn range_start(request: &FakeRequest) -> Option<usize> {
+    request.headers.get("range").and_then(|x| {
+        x.trim_start_matches("bytes=")
+            .trim_end_matches('-')
+            .parse()
+            .ok()
+    })
+}
+
+// A successful response is served partially like a static file when `range_start` is
+// given. Only `body_limit` bytes of the body are sent if it's given
+fn write_response(
+    mut stream: &TcpStream,
+    response: &FakeResponse,
+    range_start: Option<usize>,
+    body_limit: Option<usize>,
+) -> io::Result<()> {
+    let len = response.body.len();
+    let (status, content_range, body) = match range_start {
+        Some(start) if response.status == 200 && start < len => (
+            206,
+            Some(format!("bytes {}-{}/{}", start, len - 1, len)),
+            &response.body[start..],
+        ),
+        Some(_) if response.status == 200 => (416, Some(format!("bytes */{}", len)), &[][..]),
+        _ => (response.status, None, &response.body[..]),
+    };
+    let reason = match status {
+        200 => "OK",
+        206 => "Partial Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        409 => "Conflict",
+        416 => "Range Not Satisfiable",
+        422 => "Unprocessable Entity",
+        _ => "Unknown",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+        status,
+        reason,
+        response.content_type,
+        body.len()
+    )?;
+    if let Some(content_range) = content_range {
+        write!(stream, "Content-Range: {}\r\n", content_range)?;
+    }
+    write!(stream, "Connection: close\r\n\r\n")?;
+    match body_limit {
+        Some(limit) if limit < body.len() => stream.write_all(&body[..limit])?,
+        _ => stream.write_all(body)?,
+    }
+    stream.flush()
+}
+
+fn handle_connection(stream: TcpStream, state: &Mutex<FakeState>) -> io::Result<()> {
+    let request = read_request(&stream)?;
+    let mut body_limit = None;
+    let response = if request
+        .headers
+        .get("authorization")
+        .map_or(true, |x| !x.starts_with("TD1 "))
+    {
+        error_response(401, "Authentication failed")
+    } else {
+        let mut state = state.lock().unwrap();
+        let response = route(&mut state, &request);
+        if request
+            .path
+            .starts_with(&["v3".to_string(), "job".to_string(), "result".to_string()])
+        {
+            let (times, after_bytes) = state.job_result_interruptions;
+            if times > 0 {
+                state.job_result_interruptions = (times - 1, after_bytes);
+                body_limit = Some(after_bytes);
+            }
+        }
+        response
+    };
+    write_response(&stream, &response, range_start(&request), body_limit)
+}
+
+fn json_response(json: Json) -> FakeResponse {
+    FakeResponse {
+        status: 200,
+        content_type: "application/json",
+        body: json.to_string().into_bytes(),
+    }
+}
+
+fn error_response(status: u16, message: &str) -> FakeResponse {
+    let mut body = BTreeMap::new();
+    body.insert("error".to_string(), message.to_json());
+    body.insert("message".to_string(), message.to_json());
+    body.insert("severity".to_string(), "error".to_json());
+    FakeResponse {
+        status: status,
+        content_type: "application/json",
+        body: Json::Object(body).to_string().into_bytes(),
+    }
+}
+
+fn route(state: &mut FakeState, request: &FakeRequest) -> FakeResponse {
+    let path: Vec<&str> = request.path.iter().map(|x| x.as_str()).collect();
+    match (request.method.as_str(), &path[..]) {
+        ("GET", ["v3", "user", "list"]) => list_users(state),
+        ("GET", ["v3", "user", "show"]) => show_current_user(state, request),
+        ("GET", ["v3", "user", "show", email]) => show_user(state, email),
+        ("POST", ["v3", "user", "add", name]) => add_user(state, name, &request.body),
+        ("POST", ["v3", "user", "remove", name]) => remove_user(state, name),
+        ("GET", ["v3", "user", "apikey", "list", name]) => list_api_keys(state, name),
+        ("POST", ["v3", "user", "apikey", "add", name]) => add_api_key(state, name),
+        ("POST", ["v3", "user", "apikey", "remove", name]) => {
+            remove_api_key(state, name, &request.body)
+        }
+        ("GET", ["v3", "database", "list"]) => list_databases(state),
+        ("GET", ["v3", "database", "show", db]) => show_database(state, db),
+        ("POST", ["v3", "database", "create", db]) => create_database(state, db),
+        ("POST", ["v3", "database", "delete", db]) => delete_database(state, db),
+        ("POST", ["v3", "database", "update", db]) => update_database(state, db, &request.body),
+        ("GET", ["v3", "database", "access", "list", db]) => list_database_access(state, db),
+        ("POST", ["v3", "database", "access", action, db]) => {
+            update_database_access(state, db, action, &request.body)
+        }
+        ("GET", ["v3", "table", "list", db]) => list_tables(state, db),
+        ("GET", ["v3", "table", "show", db, table]) => show_table(state, db, table),
+        ("POST", ["v3", "table", "create", db, table, "log"]) => {
+            create_table(state, db, table, &request.body)
+        }
+        ("POST", ["v3", "table", "update", db, table]) => {
+            update_table(state, db, table, &request.body)
+        }
+        ("POST", ["v3", "table", "delete", db, table]) => delete_table(state, db, table),
+        ("POST", ["v3", "table", "rename", db, table, new_name]) => {
+            rename_table(state, db, table, new_name)
+        }
+        ("POST", ["v3", "table", "swap", db, table_a, table_b]) => {
+            swap_table(state, db, table_a, table_b)
+        }
+        ("POST", ["v3", "table", "append-schema", db, table]) => {
+            update_schema(state, db, table, &request.body, true)
+        }
+        ("POST", ["v3", "table", "update-schema", db, table]) => {
+            update_schema(state, db, table, &request.body, false)
+        }
+        ("PUT", ["v3", "table", "import", db, table, "msgpack.gz"]) => {
+            import(state, db, table, None, &request.body)
+        }
+        ("PUT", ["v3", "table", "import_with_id", db, table, unique_id, "msgpack.gz"]) => {
+            import(state, db, table, Some(unique_id), &request.body)
+        }
+        ("POST", ["v3", "job", "issue", job_type, db]) => {
+            issue_job(state, job_type, db, &request.body)
+        }
+        ("GET", ["v3", "job", "list"]) => list_jobs(state, &request.params),
+        ("GET", ["v3", "job", "show", job_id]) => show_job(state, job_id),
+        ("GET", ["v3", "job", "status", job_id]) => job_status(state, job_id),
+        ("GET", ["v3", "job", "result", job_id]) => job_result(state, job_id, &request.params),
+        ("POST", ["v3", "job", "kill", job_id]) => kill_job(state, job_id),
+        ("GET", ["v3", "table", "tail", db, table]) => {
+            tail_table(state, db, table, &request.params)
+        }
+        ("POST", ["v3", "table", "partialdelete", db, table]) => {
+            partial_delete(state, db, table, &request.body)
+        }
+        ("POST", ["v3", "export", "run", db, table]) => {
+            export_table(state, db, table, &request.body)
+        }
+        _ => error_response(404, "Resource not found"),
+    }
+}
+
+fn user_json(name: &str, user: &FakeUser) -> Json {
+    let mut json = BTreeMap::new();
+    json.insert("id".to_string(), user.id.to_json());
+    json.insert("name".to_string(), name.to_json());
+    json.insert("email".to_string(), user.email.to_json());
+    json.insert("organization".to_string(), Json::Null);
+    json.insert("administrator".to_string(), (user.id == 0).to_json());
+    json.insert("created_at".to_string(), user.created_at.to_json());
+    json.insert("updated_at".to_string(), user.created_at.to_json());
+    Json::Object(json)
+}
+
+fn list_users(state: &FakeState) -> FakeResponse {
+    let users = state
+        .users
+        .iter()
+        .map(|(name, user)| user_json(name, user))
+        .collect();
+    let mut json = BTreeMap::new();
+    json.insert("users".to_string(), Json::Array(users));
+    json_response(Json::Object(json))
+}
+
+fn show_user(state: &FakeState, email: &str) -> FakeResponse {
+    match state.users.iter().find(|&(_, user)| user.email == email) {
+        Some((name, user)) => json_response(user_json(name, user)),
+        None => error_response(404, &format!("User {} does not exist", email)),
+    }
+}
+
+// Any API key is accepted. Keys which aren't issued through the API belong to `fake`
+// like jobs do
+fn show_current_user(state: &mut FakeState, request: &FakeRequest) -> FakeResponse {
+    let api_key = request
+        .headers
+        .get("authorization")
+        .map(|x| x.trim_start_matches("TD1 ").to_string())
+        .unwrap_or_default();
+    if let Some((name, user)) = state
+        .users
+        .iter()
+        .find(|&(_, user)| user.api_keys.contains(&api_key))
+    {
+        return json_response(user_json(name, user));
+    }
+    let user = FakeUser {
+        id: 0,
+        email: "fake@example.com".to_string(),
+        api_keys: vec![api_key],
+        created_at: now(),
+    };
+    json_response(user_json("fake", &user))
+}
+
+fn add_user(state: &mut FakeState, name: &str, body: &[u8]) -> FakeResponse {
+    if state.users.contains_key(name) {
+        return error_response(409, &format!("User {} already exists", name));
+    }
+    let email = match Json::from_str(&String::from_utf8_lossy(body))
+        .ok()
+        .and_then(|json| {
+            json.find("email")
+                .and_then(|x| x.as_string().map(|x| x.to_string()))
+        }) {
+        Some(email) => email,
+        None => return error_response(400, "email is required"),
+    };
+    let id = state.next_user_id;
+    state.next_user_id += 1;
+    let user = FakeUser {
+        id: id,
+        email: email,
+        api_keys: vec![format!("{}/{:040x}", id, id)],
+        created_at: now(),
+    };
+    let response = json_response(user_json(name, &user));
+    state.users.insert(name.to_string(), user);
+    response
+}
+
+fn remove_user(state: &mut FakeState, name: &str) -> FakeResponse {
+    match state.users.remove(name) {
+        Some(user) => json_response(user_json(name, &user)),
+        None => error_response(404, &format!("User {} does not exist", name)),
+    }
+}
+
+fn api_keys_response(name: &str, user: &FakeUser) -> FakeResponse {
+    let mut json = BTreeMap::new();
+    json.insert("name".to_string(), name.to_json());
+    json.insert("apikeys".to_string(), user.api_keys.to_json());
+    json_response(Json::Object(json))
+}
+
+fn list_api_keys(state: &FakeState, name: &str) -> FakeResponse {
+    match state.users.get(name) {
+        Some(user) => api_keys_response(name, user),
+        None => error_response(404, &format!("User {} does not exist", name)),
+    }
+}
+
+fn add_api_key(state: &mut FakeState, name: &str) -> FakeResponse {
+    let user = match state.users.get_mut(name) {
+        Some(user) => user,
+        None => return error_response(404, &format!("User {} does not exist", name)),
+    };
+    let api_key = format!(
+        "{}/{:040x}",
+        user.id,
+        user.id * 1000 + user.api_keys.len() as u64
+    );
+    user.api_keys.push(api_key.clone());
+    let mut json = BTreeMap::new();
+    json.insert("name".to_string(), name.to_json());
+    json.insert("apikey".to_string(), api_key.to_json());
+    json_response(Json::Object(json))
+}
+
+fn remove_api_key(state: &mut FakeState, name: &str, body: &[u8]) -> FakeResponse {
+    let api_key = Json::from_str(&String::from_utf8_lossy(body))
+        .ok()
+        .and_then(|json| {
+            json.find("apikey")
+                .and_then(|x| x.as_string().map(|x| x.to_string()))
+        });
+    let user = match state.users.get_mut(name) {
+        Some(user) => user,
+        None => return error_response(404, &format!("User {} does not exist", name)),
+    };
+    match api_key.and_then(|x| user.api_keys.iter().position(|k| *k == x)) {
+        Some(i) => {
+            user.api_keys.remove(i);
+            api_keys_response(name, user)
+        }
+        None => error_response(404, "API key does not exist"),
+    }
+}
+
+fn database_json(name: &str, db: &FakeDatabase) -> Json {
+    let count: usize = db.tables.values().map(|t| t.rows.len()).sum();
+    let mut json = BTreeMap::new();
+    json.insert("name".to_string(), name.to_json());
+    json.insert("count".to_string(), (count as u64).to_json());
+    json.insert("created_at".to_string(), db.created_at.to_json());
+    json.insert("updated_at".to_string(), db.updated_at.to_json());
+    json.insert("permission".to_string(), "administrator".to_json());
+    json.insert("description".to_string(), db.description.to_json());
+    Json::Object(json)
+}
+
+fn schema_json(schema: &[(String, String)]) -> Json {
+    Json::Array(
+        schema
+            .iter()
+            .map(|&(ref name, ref column_type)| {
+                Json::Array(vec![name.to_json(), column_type.to_json()])
+            })
+            .collect(),
+    )
+}
+
+fn table_json(name: &str, table: &FakeTable) -> Json {
+    let mut json = BTreeMap::new();
+    json.insert("name".to_string(), name.to_json());
+    json.insert(
+        "schema".to_string(),
+        schema_json(&table.schema).to_string().to_json(),
+    );
+    json.insert("count".to_string(), (table.rows.len() as u64).to_json());
+    json.insert("created_at".to_string(), table.created_at.to_json());
+    json.insert("updated_at".to_string(), table.updated_at.to_json());
+    json.insert("estimated_storage_size".to_string(), 0u64.to_json());
+    json.insert("last_import".to_string(), table.last_import.to_json());
+    json.insert(
+        "last_log_timestamp".to_string(),
+        table.last_import.to_json(),
+    );
+    json.insert(
+        "expire_days".to_string(),
+        table.expire_days.map(|x| x as u64).to_json(),
+    );
+    json.insert("include_v".to_string(), table.include_v.to_json());
+    Json::Object(json)
+}
+
+fn list_databases(state: &FakeState) -> FakeResponse {
+    let databases = state
+        .databases
+        .iter()
+        .map(|(name, db)| database_json(name, db))
+        .collect();
+    let mut json = BTreeMap::new();
+    json.insert("databases".to_string(), Json::Array(databases));
+    json_response(Json::Object(json))
+}
+
+fn show_database(state: &FakeState, name: &str) -> FakeResponse {
+    match state.databases.get(name) {
+        Some(db) => json_response(database_json(name, db)),
+        None => error_response(404, &format!("Database {} does not exist", name)),
+    }
+}
+
+fn create_database(state: &mut FakeState, name: &str) -> FakeResponse {
+    if state.databases.contains_key(name) {
+        return error_response(409, &format!("Database {} already exists", name));
+    }
+    state.databases.insert(name.to_string(), new_database());
+    let mut json = BTreeMap::new();
+    json.insert("database".to_string(), name.to_json());
+    json_response(Json::Object(json))
+}
+
+fn delete_database(state: &mut FakeState, name: &str) -> FakeResponse {
+    if state.databases.remove(name).is_none() {
+        return error_response(404, &format!("Database {} does not exist", name));
+    }
+    let mut json = BTreeMap::new();
+    json.insert("database".to_string(), name.to_json());
+    json_response(Json::Object(json))
+}
+
+fn update_database(state: &mut FakeState, name: &str, body: &[u8]) -> FakeResponse {
+    let db = match state.databases.get_mut(name) {
+        Some(db) => db,
+        None => return error_response(404, &format!("Database {} does not exist", name)),
+    };
+    if let Some(description) = Json::from_str(&String::from_utf8_lossy(body))
+        .ok()
+        .and_then(|json| json.find("description").cloned())
+    {
+        db.description = description.as_string().map(|x| x.to_string());
+    }
+    db.updated_at = now();
+    let mut json = BTreeMap::new();
+    json.insert("database".to_string(), name.to_json());
+    json_response(Json::Object(json))
+}
+
+fn list_database_access(state: &FakeState, name: &str) -> FakeResponse {
+    let db = match state.databases.get(name) {
+        Some(db) => db,
+        None => return error_response(404, &format!("Database {} does not exist", name)),
+    };
+    let access = db
+        .access
+        .iter()
+        .map(|(user, permission)| {
+            let mut json = BTreeMap::new();
+            json.insert("user".to_string(), user.to_json());
+            json.insert("permission".to_string(), permission.to_json());
+            Json::Object(json)
+        })
+        .collect();
+    let mut json = BTreeMap::new();
+    json.insert("database".to_string(), name.to_json());
+    json.insert("access".to_string(), Json::Array(access));
+    json_response(Json::Object(json))
+}
+
+fn update_database_access(
+    state: &mut FakeState,
+    name: &str,
+    action: &str,
+    body: &[u8],
+) -> FakeResponse {
+    let params = Json::from_str(&String::from_utf8_lossy(body)).ok();
+    let param = |key: &str| {
+        params
+            .as_ref()
+            .and_then(|x| x.find(key))
+            .and_then(|x| x.as_string())
+            .map(|x| x.to_string())
+    };
+    let db = match state.databases.get_mut(name) {
+        Some(db) => db,
+        None => return error_response(404, &format!("Database {} does not exist", name)),
+    };
+    match (action, param("user"), param("permission")) {
+        ("grant", Some(user), Some(permission)) => {
+            if let Ok(Permission::Unknown(_)) = Permission::from_str(&permission) {
+                return error_response(400, &format!("Invalid permission {}", permission));
+            }
+            db.access.insert(user, permission);
+        }
+        ("revoke", Some(user), _) => {
+            if db.access.remove(&user).is_none() {
+                return error_response(404, &format!("User {} doesn't have access", user));
+            }
+        }
+        ("grant", _, _) | ("revoke", _, _) => {
+            return error_response(400, "user and permission are required")
+        }
+        _ => return error_response(404, "Resource not found"),
+    }
+    list_database_access(state, name)
+}
+
+fn list_tables(state: &FakeState, database_name: &str) -> FakeResponse {
+    let db = match state.databases.get(database_name) {
+        Some(db) => db,
+        None => return error_response(404, &format!("Database {} does not exist", database_name)),
+    };
+    let tables = db
+        .tables
+        .iter()
+        .map(|(name, table)| table_json(name, table))
+        .collect();
+    let mut json = BTreeMap::new();
+    json.insert("database".to_string(), database_name.to_json());
+    json.insert("tables".to_string(), Json::Array(tables));
+    json_response(Json::Object(json))
+}
+
+fn show_table(state: &FakeState, database_name: &str, name: &str) -> FakeResponse {
+    match state
+        .databases
+        .get(database_name)
+        .and_then(|db| db.tables.get(name))
+    {
+        Some(table) => json_response(table_json(name, table)),
+        None => error_response(404, &format!("Table {} does not exist", name)),
+    }
+}
+
+fn table_response(database_name: &str, name: &str) -> FakeResponse {
+    let mut json = BTreeMap::new();
+    json.insert("database".to_string(), database_name.to_json());
+    json.insert("table".to_string(), name.to_json());
+    json_response(Json::Object(json))
+}
+
+// Applies `expire_days`, `include_v`, `schema` and `detect_schema` in the parameters
+fn apply_table_options(table: &mut FakeTable, body: &[u8]) -> Result<(), FakeResponse> {
+    let params = match Json::from_str(&String::from_utf8_lossy(body)) {
+        Ok(Json::Object(params)) => params,
+        _ if body.is_empty() => return Ok(()),
+        _ => return Err(error_response(400, "Invalid parameters")),
+    };
+    let param = |key: &str| params.get(key).and_then(|x| x.as_string());
+
+    if let Some(expire_days) = param("expire_days") {
+        match expire_days.parse::<u32>() {
+            Ok(expire_days) => table.expire_days = Some(expire_days),
+            Err(_) => return Err(error_response(400, "Invalid expire_days")),
+        }
+    }
+    if let Some(include_v) = param("include_v") {
+        table.include_v = include_v == "true";
+    }
+    if let Some(schema) = param("schema") {
+        match Json::from_str(schema).ok().and_then(|x| parse_schema(&x)) {
+            Some(schema) => table.schema = schema,
+            None => return Err(error_response(400, "Invalid schema")),
+        }
+    }
+    if param("detect_schema") == Some("true") {
+        table.schema.clear();
+        let rows = table.rows.clone();
+        extend_schema(table, &rows);
+    }
+    table.updated_at = now();
+    Ok(())
+}
+
+fn create_table(
+    state: &mut FakeState,
+    database_name: &str,
+    name: &str,
+    body: &[u8],
+) -> FakeResponse {
+    let db = match state.databases.get_mut(database_name) {
+        Some(db) => db,
+        None => return error_response(404, &format!("Database {} does not exist", database_name)),
+    };
+    if db.tables.contains_key(name) {
+        return error_response(409, &format!("Table {} already exists", name));
+    }
+    let mut table = new_table();
+    if let Err(response) = apply_table_options(&mut table, body) {
+        return response;
+    }
+    db.tables.insert(name.to_string(), table);
+    table_response(database_name, name)
+}
+
+fn update_table(
+    state: &mut FakeState,
+    database_name: &str,
+    name: &str,
+    body: &[u8],
+) -> FakeResponse {
+    let table = match state
+        .databases
+        .get_mut(database_name)
+        .and_then(|db| db.tables.get_mut(name))
+    {
+        Some(table) => table,
+        None => return error_response(404, &format!("Table {} does not exist", name)),
+    };
+    match apply_table_options(table, body) {
+        Ok(()) => table_response(database_name, name),
+        Err(response) => response,
+    }
+}
+
+fn delete_table(state: &mut FakeState, database_name: &str, name: &str) -> FakeResponse {
+    match state
+        .databases
+        .get_mut(database_name)
+        .and_then(|db| db.tables.remove(name))
+    {
+        Some(_) => table_response(database_name, name),
+        None => error_response(404, &format!("Table {} does not exist", name)),
+    }
+}
+
+fn rename_table(
+    state: &mut FakeState,
+    database_name: &str,
+    name: &str,
+    new_name: &str,
+) -> FakeResponse {
+    let db = match state.databases.get_mut(database_name) {
+        Some(db) => db,
+        None => return error_response(404, &format!("Database {} does not exist", database_name)),
+    };
+    if db.tables.contains_key(new_name) {
+        return error_response(409, &format!("Table {} already exists", new_name));
+    }
+    match db.tables.remove(name) {
+        Some(table) => {
+            db.tables.insert(new_name.to_string(), table);
+            table_response(database_name, new_name)
+        }
+        None => error_response(404, &format!("Table {} does not exist", name)),
+    }
+}
+
+fn swap_table(
+    state: &mut FakeState,
+    database_name: &str,
+    name_a: &str,
+    name_b: &str,
+) -> FakeResponse {
+    let db = match state.databases.get_mut(database_name) {
+        Some(db) => db,
+        None => return error_response(404, &format!("Database {} does not exist", database_name)),
+    };
+    match (db.tables.remove(name_a), db.tables.remove(name_b)) {
+        (Some(table_a), Some(table_b)) => {
+            db.tables.insert(name_a.to_string(), table_b);
+            db.tables.insert(name_b.to_string(), table_a);
+            table_response(database_name, name_a)
+        }
+        (table_a, table_b) => {
+            // Put back the one which existed
+            if let Some(table_a) = table_a {
+                db.tables.insert(name_a.to_string(), table_a);
+            }
+            if let Some(table_b) = table_b {
+                db.tables.insert(name_b.to_string(), table_b);
+            }
+            error_response(404, "Table does not exist")
+        }
+    }
+}
+
+fn parse_schema(json: &Json) -> Option<Vec<(String, String)>> {
+    json.as_array().and_then(|columns| {
+        columns
+            .iter()
+            .map(|column| match column.as_array() {
+                Some(xs) if xs.len() >= 2 => match (xs[0].as_string(), xs[1].as_string()) {
+                    (Some(name), Some(column_type)) => {
+                        Some((name.to_string(), column_type.to_string()))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+fn update_schema(
+    state: &mut FakeState,
+    database_name: &str,
+    name: &str,
+    body: &[u8],
+    append: bool,
+) -> FakeResponse {
+    let schema = Json::from_str(&String::from_utf8_lossy(body))
+        .ok()
+        .and_then(|json| json.find("schema").cloned())
+        .and_then(|schema| match schema {
+            // `update-schema` takes the schema as a JSON string
+            Json::String(s) => Json::from_str(&s).ok().and_then(|x| parse_schema(&x)),
+            json => parse_schema(&json),
+        });
+    let schema = match schema {
+        Some(schema) => schema,
+        None => return error_response(400, "Invalid schema"),
+    };
+    let table = match state
+        .databases
+        .get_mut(database_name)
+        .and_then(|db| db.tables.get_mut(name))
+    {
+        Some(table) => table,
+        None => return error_response(404, &format!("Table {} does not exist", name)),
+    };
+    if append {
+        table.schema.extend(schema);
+    } else {
+        table.schema = schema;
+    }
+    table.updated_at = now();
+    table_response(database_name, name)
+}
+
+fn guess_column_type(value: &Value) -> &'static str {
+    match *value {
+        Value::Integer(_) => "long",
+        Value::Float(_) => "double",
+        Value::Array(_) => "array<string>",
+        _ => "string",
+    }
+}
+
+// Columns appearing first are added to the schema like the schema auto detection does
+fn extend_schema(table: &mut FakeTable, rows: &[Value]) {
+    for row in rows {
+        if let Value::Map(ref kvs) = *row {
+            for &(ref k, ref v) in kvs {
+                if let Value::String(ref key) = *k {
+                    if key != "time" && !table.schema.iter().any(|&(ref n, _)| n == key) {
+                        table
+                            .schema
+                            .push((key.clone(), guess_column_type(v).to_string()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn import(
+    state: &mut FakeState,
+    database_name: &str,
+    name: &str,
+    unique_id: Option<&str>,
+    body: &[u8],
+) -> FakeResponse {
+    let table = match state
+        .databases
+        .get_mut(database_name)
+        .and_then(|db| db.tables.get_mut(name))
+    {
+        Some(table) => table,
+        None => return error_response(404, &format!("Table {} does not exist", name)),
+    };
+    if let Some(unique_id) = unique_id {
+        if table.unique_ids.iter().any(|x| x == unique_id) {
+            return table_response(database_name, name);
+        }
+    }
+
+    let mut rows = vec![];
+    let mut d = GzDecoder::new(body);
+    loop {
+        match ::rmpv::decode::read_value(&mut d) {
+            Ok(x @ ::rmpv::Value::Map(_)) => rows.push(Value::from(x)),
+            Ok(_) => return error_response(400, "Each record must be a map"),
+            Err(::rmpv::decode::Error::InvalidMarkerRead(ref err))
+                if err.kind() == ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(err) => return error_response(400, &format!("Invalid msgpack.gz: {}", err)),
+        }
+    }
+
+    extend_schema(table, &rows);
+
+    table.rows.extend(rows);
+    if let Some(unique_id) = unique_id {
+        table.unique_ids.push(unique_id.to_string());
+    }
+    table.last_import = Some(now());
+    table.updated_at = now();
+    table_response(database_name, name)
+}
+
+fn parse_job_id(state: &FakeState, job_id: &str) -> Result<u64, FakeResponse> {
+    match job_id.parse::<u64>() {
+        Ok(job_id) if state.jobs.contains_key(&job_id) => Ok(job_id),
+        _ => Err(error_response(
+            404,
+            &format!("Job {} does not exist", job_id),
+        )),
+    }
+}
+
+fn issue_job(
+    state: &mut FakeState,
+    job_type: &str,
+    database_name: &str,
+    body: &[u8],
+) -> FakeResponse {
+    if !state.databases.contains_key(database_name) {
+        return error_response(404, &format!("Database {} does not exist", database_name));
+    }
+    let query = match Json::from_str(&String::from_utf8_lossy(body))
+        .ok()
+        .and_then(|json| {
+            json.find("query")
+                .and_then(|x| x.as_string().map(|s| s.to_string()))
+        }) {
+        Some(query) => query,
+        None => return error_response(400, "Query is required"),
+    };
+    let result = state
+        .query_results
+        .iter()
+        .find(|&&(ref fragment, _)| query.contains(fragment.as_str()))
+        .map(|&(_, ref result)| result.clone())
+        .unwrap_or_else(|| state.default_result.clone());
+
+    let job_id = state.next_job_id;
+    state.next_job_id += 1;
+    state.jobs.insert(
+        job_id,
+        FakeJob {
+            job_type: job_type.to_string(),
+            database: database_name.to_string(),
+            query: query,
+            result: result,
+            polls: 0,
+            killed: false,
+            created_at: now(),
+        },
+    );
+
+    let mut json = BTreeMap::new();
+    json.insert("job_id".to_string(), job_id.to_string().to_json());
+    json.insert("database".to_string(), database_name.to_json());
+    json_response(Json::Object(json))
+}
+
+fn job_json(job_id: u64, job: &FakeJob) -> Json {
+    let status = job.status();
+    let finished = status.is_finished();
+    let mut json = BTreeMap::new();
+    json.insert("job_id".to_string(), job_id.to_string().to_json());
+    json.insert("type".to_string(), job.job_type.to_json());
+    json.insert("database".to_string(), job.database.to_json());
+    json.insert("query".to_string(), job.query.to_json());
+    json.insert("status".to_string(), status.to_string().to_json());
+    json.insert(
+        "url".to_string(),
+        format!("https://console.treasuredata.com/jobs/{}", job_id).to_json(),
+    );
+    json.insert("created_at".to_string(), job.created_at.to_json());
+    json.insert("start_at".to_string(), job.created_at.to_json());
+    json.insert(
+        "end_at".to_string(),
+        if finished {
+            job.created_at.to_json()
+        } else {
+            "".to_json()
+        },
+    );
+    json.insert("cpu_time".to_string(), Json::Null);
+    json.insert(
+        "result_size".to_string(),
+        (job.result.rows.len() as u64).to_json(),
+    );
+    json.insert(
+        "hive_result_schema".to_string(),
+        if job.result.result_schema.is_empty() {
+            Json::Null
+        } else {
+            schema_json(&job.result.result_schema).to_string().to_json()
+        },
+    );
+    json.insert(
+        "num_records".to_string(),
+        if finished {
+            (job.result.rows.len() as u64).to_json()
+        } else {
+            Json::Null
+        },
+    );
+    json.insert("result".to_string(), "".to_json());
+    json.insert("linked_result_export_job_id".to_string(), Json::Null);
+    json.insert("organization".to_string(), Json::Null);
+    json.insert("user_name".to_string(), "fake".to_json());
+    let mut debug = BTreeMap::new();
+    debug.insert("cmdout".to_string(), "".to_json());
+    debug.insert("stderr".to_string(), job.result.stderr.to_json());
+    json.insert("debug".to_string(), Json::Object(debug));
+    json.insert("priority".to_string(), 0u64.to_json());
+    json.insert("retry_limit".to_string(), 0u64.to_json());
+    json.insert(
+        "duration".to_string(),
+        if finished { 0u64.to_json() } else { Json::Null },
+    );
+    Json::Object(json)
+}
+
+fn list_jobs(state: &FakeState, params: &BTreeMap<String, String>) -> FakeResponse {
+    let from = params.get("from").and_then(|x| x.parse::<usize>().ok());
+    let to = params.get("to").and_then(|x| x.parse::<usize>().ok());
+    let status = params.get("status");
+
+    // Newer jobs come first like the actual API
+    let jobs: Vec<Json> = state
+        .jobs
+        .iter()
+        .rev()
+        .filter(|&(_, job)| status.map_or(true, |s| *s == job.status().to_string()))
+        .enumerate()
+        .filter(|&(i, _)| from.map_or(true, |x| i >= x) && to.map_or(true, |x| i <= x))
+        .map(|(_, (job_id, job))| job_json(*job_id, job))
+        .collect();
+
+    let mut json = BTreeMap::new();
+    json.insert("count".to_string(), (jobs.len() as u64).to_json());
+    json.insert("from".to_string(), from.map(|x| x as u64).to_json());
+    json.insert("to".to_string(), to.map(|x| x as u64).to_json());
+    json.insert("jobs".to_string(), Json::Array(jobs));
+    json_response(Json::Object(json))
+}
+
+fn show_job(state: &mut FakeState, job_id: &str) -> FakeResponse {
+    let job_id = match parse_job_id(state, job_id) {
+        Ok(job_id) => job_id,
+        Err(response) => return response,
+    };
+    let job = state.jobs.get_mut(&job_id).unwrap();
+    job.polls += 1;
+    json_response(job_json(job_id, job))
+}
+
+fn job_status(state: &mut FakeState, job_id: &str) -> FakeResponse {
+    let job_id = match parse_job_id(state, job_id) {
+        Ok(job_id) => job_id,
+        Err(response) => return response,
+    };
+    let job = state.jobs.get_mut(&job_id).unwrap();
+    job.polls += 1;
+    let job_json = job_json(job_id, job);
+    let mut json = BTreeMap::new();
+    for key in &[
+        "job_id",
+        "status",
+        "created_at",
+        "start_at",
+        "end_at",
+        "duration",
+    ] {
+        if let Some(x) = job_json.find(key) {
+            json.insert(key.to_string(), x.clone());
+        }
+    }
+    json_response(Json::Object(json))
+}
+
+fn job_result(state: &FakeState, job_id: &str, params: &BTreeMap<String, String>) -> FakeResponse {
+    let job_id = match parse_job_id(state, job_id) {
+        Ok(job_id) => job_id,
+        Err(response) => return response,
+    };
+    let job = &state.jobs[&job_id];
+    match job.status() {
+        JobStatus::Success => (),
+        status => return error_response(422, &format!("Job {} is {}", job_id, status.to_string())),
+    }
+
+    let mut msgpack = vec![];
+    for row in &job.result.rows {
+        Value::Array(row.clone())
+            .write_msgpack(&mut msgpack)
+            .unwrap();
+    }
+
+    match params.get("format").map(|x| x.as_str()) {
+        Some("msgpack_gz") => {
+            let mut e = GzEncoder::new(vec![], Compression::default());
+            e.write_all(&msgpack).unwrap();
+            FakeResponse {
+                status: 200,
+                content_type: "application/octet-stream",
+                body: e.finish().unwrap(),
+            }
+        }
+        Some("msgpack") => FakeResponse {
+            status: 200,
+            content_type: "application/x-msgpack",
+            body: msgpack,
+        },
+        _ => error_response(400, "Unsupported format"),
+    }
+}
+
+fn kill_job(state: &mut FakeState, job_id: &str) -> FakeResponse {
+    let job_id = match parse_job_id(state, job_id) {
+        Ok(job_id) => job_id,
+        Err(response) => return response,
+    };
+    let job = state.jobs.get_mut(&job_id).unwrap();
+    let former_status = job.status();
+    match former_status {
+        JobStatus::Queued | JobStatus::Running => job.killed = true,
+        _ => (),
+    }
+    let mut json = BTreeMap::new();
+    json.insert("job_id".to_string(), job_id.to_string().to_json());
+    json.insert(
+        "former_status".to_string(),
+        former_status.to_string().to_json(),
+    );
+    json_response(Json::Object(json))
+}
+
+fn to_json(value: &Value) -> Json {
+    match *value {
+        Value::Nil => Json::Null,
+        Value::Boolean(x) => Json::Boolean(x),
+        Value::Integer(Integer::I64(x)) => Json::I64(x),
+        Value::Integer(Integer::U64(x)) => Json::U64(x),
+        Value::Float(Float::F32(x)) => Json::F64(x as f64),
+        Value::Float(Float::F64(x)) => Json::F64(x),
+        Value::String(ref x) => Json::String(x.clone()),
+        Value::Binary(ref x) | Value::Ext(_, ref x) => {
+            Json::String(String::from_utf8_lossy(x).into_owned())
+        }
+        Value::Array(ref xs) => Json::Array(xs.iter().map(to_json).collect()),
+        Value::Map(ref xs) => Json::Object(
+            xs.iter()
+                .map(|&(ref k, ref v)| {
+                    let key = match *k {
+                        Value::String(ref k) => k.clone(),
+                        ref k => to_json(k).to_string(),
+                    };
+                    (key, to_json(v))
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn row_item<'a>(row: &'a Value, column: &str) -> Option<&'a Value> {
+    match *row {
+        Value::Map(ref kvs) => kvs
+            .iter()
+            .find(|&&(ref k, _)| match *k {
+                Value::String(ref k) => k == column,
+                _ => false,
+            })
+            .map(|&(_, ref v)| v),
+        _ => None,
+    }
+}
+
+fn row_time(row: &Value) -> i64 {
+    match row_item(row, "time") {
+        Some(&Value::Integer(Integer::I64(x))) => x,
+        Some(&Value::Integer(Integer::U64(x))) => x as i64,
+        _ => 0,
+    }
+}
+
+fn tail_table(
+    state: &FakeState,
+    database_name: &str,
+    name: &str,
+    params: &BTreeMap<String, String>,
+) -> FakeResponse {
+    let table = match state
+        .databases
+        .get(database_name)
+        .and_then(|db| db.tables.get(name))
+    {
+        Some(table) => table,
+        None => return error_response(404, &format!("Table {} does not exist", name)),
+    };
+    if params.get("format").map_or(false, |x| x != "msgpack") {
+        return error_response(400, "Unsupported format");
+    }
+    let count = params
+        .get("count")
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(10);
+    let from = params.get("from").and_then(|x| x.parse::<i64>().ok());
+    let to = params.get("to").and_then(|x| x.parse::<i64>().ok());
+
+    let rows: Vec<&Value> = table
+        .rows
+        .iter()
+        .filter(|row| {
+            let time = row_time(row);
+            from.map_or(true, |x| time >= x) && to.map_or(true, |x| time < x)
+        })
+        .collect();
+    let mut msgpack = vec![];
+    for row in &rows[rows.len().saturating_sub(count)..] {
+        row.write_msgpack(&mut msgpack).unwrap();
+    }
+    FakeResponse {
+        status: 200,
+        content_type: "application/x-msgpack",
+        body: msgpack,
+    }
+}
+
+fn partial_delete(
+    state: &mut FakeState,
+    database_name: &str,
+    name: &str,
+    body: &[u8],
+) -> FakeResponse {
+    let params = Json::from_str(&String::from_utf8_lossy(body)).ok();
+    let param = |key: &str| {
+        params
+            .as_ref()
+            .and_then(|x| x.find(key))
+            .and_then(|x| x.as_string())
+            .and_then(|x| x.parse::<i64>().ok())
+    };
+    let (from, to) = match (param("from"), param("to")) {
+        (Some(from), Some(to)) if from % 3600 == 0 && to % 3600 == 0 && from < to => (from, to),
+        _ => return error_response(400, "from and to must be hour-aligned and from < to"),
+    };
+
+    let table = match state
+        .databases
+        .get_mut(database_name)
+        .and_then(|db| db.tables.get_mut(name))
+    {
+        Some(table) => table,
+        None => return error_response(404, &format!("Table {} does not exist", name)),
+    };
+    table.rows.retain(|row| {
+        let time = row_time(row);
+        time < from || time >= to
+    });
+    table.updated_at = now();
+
+    let job_id = state.next_job_id;
+    state.next_job_id += 1;
+    state.jobs.insert(
+        job_id,
+        FakeJob {
+            job_type: JobType::PartialDelete.to_string(),
+            database: database_name.to_string(),
+            query: "".to_string(),
+            result: FakeJobResult::success(&[], vec![]),
+            polls: 0,
+            killed: false,
+            created_at: now(),
+        },
+    );
+
+    let mut json = BTreeMap::new();
+    json.insert("job_id".to_string(), job_id.to_string().to_json());
+    json.insert("database".to_string(), database_name.to_json());
+    json.insert("table".to_string(), name.to_json());
+    json.insert("from".to_string(), from.to_json());
+    json.insert("to".to_string(), to.to_json());
+    json_response(Json::Object(json))
+}
+
+fn export_table(
+    state: &mut FakeState,
+    database_name: &str,
+    name: &str,
+    body: &[u8],
+) -> FakeResponse {
+    let params = match Json::from_str(&String::from_utf8_lossy(body)) {
+        Ok(params) => params,
+        Err(_) => return error_response(400, "Invalid parameters"),
+    };
+    let param = |key: &str| {
+        params
+            .find(key)
+            .and_then(|x| x.as_string())
+            .map(|x| x.to_string())
+    };
+    let (bucket, file_prefix, format) =
+        match (param("bucket"), param("file_prefix"), param("file_format")) {
+            (Some(bucket), Some(file_prefix), Some(format)) => (bucket, file_prefix, format),
+            _ => return error_response(400, "bucket, file_prefix and file_format are required"),
+        };
+    if param("storage_type").map_or(true, |x| x != "s3")
+        || param("access_key_id").is_none()
+        || param("secret_access_key").is_none()
+    {
+        return error_response(400, "S3 storage and its credentials are required");
+    }
+    let from = param("from").and_then(|x| x.parse::<i64>().ok());
+    let to = param("to").and_then(|x| x.parse::<i64>().ok());
+
+    let table = match state
+        .databases
+        .get(database_name)
+        .and_then(|db| db.tables.get(name))
+    {
+        Some(table) => table,
+        None => return error_response(404, &format!("Table {} does not exist", name)),
+    };
+    let rows: Vec<&Value> = table
+        .rows
+        .iter()
+        .filter(|row| {
+            let time = row_time(row);
+            from.map_or(true, |x| time >= x) && to.map_or(true, |x| time < x)
+        })
+        .collect();
+
+    let content = match ExportFormat::from_str(&format) {
+        Ok(ExportFormat::Json) => {
+            Json::Array(rows.iter().map(|row| to_json(row)).collect()).to_string()
+        }
+        Ok(ExportFormat::JsonLines) => rows
+            .iter()
+            .map(|row| format!("{}\n", to_json(row)))
+            .collect(),
+        Ok(ExportFormat::Tsv) => {
+            let mut columns = vec!["time".to_string()];
+            columns.extend(table.schema.iter().map(|&(ref name, _)| name.clone()));
+            rows.iter()
+                .map(|row| {
+                    let items: Vec<String> = columns
+                        .iter()
+                        .map(|column| match row_item(row, column) {
+                            None | Some(&Value::Nil) => "".to_string(),
+                            Some(&Value::String(ref x)) => x.clone(),
+                            Some(x) => to_json(x).to_string(),
+                        })
+                        .collect();
+                    format!("{}\n", items.join("\t"))
+                })
+                .collect()
+        }
+        Err(_) => return error_response(400, &format!("Unsupported file_format {}", format)),
+    };
+    let mut e = GzEncoder::new(vec![], Compression::default());
+    e.write_all(content.as_bytes()).unwrap();
+    let object = FakeExportedObject {
+        endpoint: param("endpoint"),
+        bucket: bucket,
+        key: format!("{}00000.{}", file_prefix, format),
+        body: e.finish().unwrap(),
+    };
+
+    let job_id = state.next_job_id;
+    state.next_job_id += 1;
+    state.jobs.insert(
+        job_id,
+        FakeJob {
+            job_type: JobType::Export.to_string(),
+            database: database_name.to_string(),
+            query: "".to_string(),
+            result: FakeJobResult::success(&[], vec![]),
+            polls: 0,
+            killed: false,
+            created_at: now(),
+        },
+    );
+    state.exported_objects.push(object);
+
+    let mut json = BTreeMap::new();
+    json.insert("job_id".to_string(), job_id.to_string().to_json());
+    json.insert("database".to_string(), database_name.to_json());
+    json_response(Json::Object(json))
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::read::GzDecoder;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::fs::File;
+    use std::io::prelude::*;
+    use std::sync::{Arc, Mutex};
+    use tempdir::TempDir;
+
+    use error::*;
+    use model::*;
+    use progress::*;
+    use table_import::*;
+    use testing::*;
+    use value::*;
+
+    const APIKEY: &'static str = "1234abcd";
+
+    #[test]
+    fn databases_and_tables() {
+        let server = FakeServer::start().unwrap();
+        let client = server.client(APIKEY);
+
+        assert!(!client.database_exists("db0").unwrap());
+        client.create_database("db0").unwrap();
+        assert!(client.create_database("db0").is_err());
+        client.create_database_if_not_exists("db0").unwrap();
+        assert!(client.database_exists("db0").unwrap());
+        assert!(!client.table_exists("db0", "tbl0").unwrap());
+        client.create_table("db0", "tbl0").unwrap();
+        assert!(client.create_table("db0", "tbl0").is_err());
+        client.create_table_if_not_exists("db0", "tbl0").unwrap();
+        assert!(client.table_exists("db0", "tbl0").unwrap());
+        client.create_table("db0", "tbl1").unwrap();
+        client.rename_table("db0", "tbl1", "tbl2").unwrap();
+        client.swap_table("db0", "tbl0", "tbl2").unwrap();
+        client
+            .append_schema("db0", "tbl0", &vec![("name", SchemaType::String)])
+            .unwrap();
+        client
+            .copy_table_schema("db0", "tbl0", "db0", "tbl2")
+            .unwrap();
+        assert_eq!(
+            r#"[["name","string"]]"#,
+            client.table("db0", "tbl2").unwrap().schema
+        );
+        match client.copy_table_schema("db0", "tbl9", "db0", "tbl2") {
+            Err(TreasureDataError::InvalidArgumentError(_)) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        let databases = client.databases().unwrap();
+        assert_eq!(1, databases.len());
+        assert_eq!("db0", databases[0].name);
+
+        let tables = client.tables("db0").unwrap();
+        assert_eq!(
+            vec!["tbl0", "tbl2"],
+            tables
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(r#"[["name","string"]]"#, tables[1].schema);
+
+        client.delete_table("db0", "tbl0").unwrap();
+        client.delete_database("db0").unwrap();
+        assert_eq!(0, client.databases().unwrap().len());
+    }
+
+    #[test]
+    fn users() {
+        let server = FakeServer::start().unwrap();
+        let client = server.client(APIKEY);
+
+        assert_eq!("fake", client.current_user().unwrap().name);
+
+        client
+            .add_user("alice", "alice@example.com", "password")
+            .unwrap();
+        assert!(client
+            .add_user("alice", "alice@example.com", "password")
+            .is_err());
+        let user = client.user("alice@example.com").unwrap();
+        assert_eq!("alice", user.name);
+        assert_eq!(1, client.users().unwrap().len());
+
+        // Rotate the API key
+        let old_keys = client.api_keys("alice").unwrap();
+        assert_eq!(1, old_keys.len());
+        let new_key = client.add_api_key("alice").unwrap();
+        client.remove_api_key("alice", &old_keys[0]).unwrap();
+        assert_eq!(vec![new_key.clone()], client.api_keys("alice").unwrap());
+
+        let alice = server.client(&new_key.to_string());
+        assert_eq!("alice@example.com", alice.current_user().unwrap().email);
+
+        client.remove_user("alice").unwrap();
+        assert!(client.user("alice@example.com").is_err());
+    }
+
+    #[test]
+    fn database_access() {
+        let server = FakeServer::start().unwrap();
+        server.create_database("db0");
+        let client = server.client(APIKEY);
+
+        client
+            .update_database("db0", &DatabaseOptions::new().description("Access logs"))
+            .unwrap();
+        let database = client.databases().unwrap().pop().unwrap();
+        assert_eq!(Permission::Administrator, database.permission);
+        assert_eq!(Some("Access logs".to_string()), database.description);
+
+        client
+            .grant_database_access("db0", "alice@example.com", Permission::QueryOnly)
+            .unwrap();
+        client
+            .grant_database_access("db0", "bob@example.com", Permission::ImportOnly)
+            .unwrap();
+        client
+            .revoke_database_access("db0", "bob@example.com")
+            .unwrap();
+        assert!(client
+            .revoke_database_access("db0", "bob@example.com")
+            .is_err());
+
+        let access = client.database_access("db0").unwrap();
+        assert_eq!(1, access.len());
+        assert_eq!("alice@example.com", access[0].user);
+        assert_eq!(Permission::QueryOnly, access[0].permission);
+    }
+
+    #[test]
+    fn import() {
+        let server = FakeServer::start().unwrap();
+        server.create_table("db0", "tbl0");
+        let client = server.client(APIKEY);
+
+        let mut chunk = TableImportWritableChunk::new().unwrap();
+        chunk.next_row(2).unwrap();
+        chunk.write_key_and_i64("time", 1470000000).unwrap();
+        chunk.write_key_and_str("name", "foo").unwrap();
+        let readable_chunk = chunk.close().unwrap();
+
+        client
+            .import_msgpack_gz_file_to_table(
+                "db0",
+                "tbl0",
+                readable_chunk.file_path.as_str(),
+                Some("id0"),
+            )
+            .unwrap();
+        // Imports with the same unique ID are ignored
+        let progress = Arc::new(Mutex::new(vec![]));
+        {
+            let progress = progress.clone();
+            client
+                .import_msgpack_gz_file_to_table_with_progress(
+                    "db0",
+                    "tbl0",
+                    readable_chunk.file_path.as_str(),
+                    Some("id0"),
+                    move |x: &TransferProgress| progress.lock().unwrap().push(*x),
+                )
+                .unwrap();
+        }
+        let file_len = fs::metadata(&readable_chunk.file_path).unwrap().len();
+        assert_eq!(
+            Some(&TransferProgress {
+                bytes: file_len,
+                total_bytes: Some(file_len),
+                rows: None,
+                total_rows: None,
+            }),
+            progress.lock().unwrap().last()
+        );
+
+        let rows = client
+            .tail_table("db0", "tbl0", Some(1), None, None)
+            .unwrap();
+        assert_eq!(rows, server.table_rows("db0", "tbl0").unwrap());
+        assert_eq!(
+            vec![Value::Map(vec![
+                (
+                    Value::String("time".to_string()),
+                    Value::Integer(Integer::I64(1470000000))
+                ),
+                (
+                    Value::String("name".to_string()),
+                    Value::String("foo".to_string())
+                ),
+            ])],
+            rows
+        );
+        assert_eq!(
+            r#"[["name","string"]]"#,
+            client.tables("db0").unwrap()[0].schema
+        );
+    }
+
+    #[test]
+    fn copy_job_result_to_table() {
+        let server = FakeServer::start().unwrap();
+        server.create_table("db0", "tbl0");
+        server.default_job_result(FakeJobResult::success(
+            &[("time", "bigint"), ("attrs", "map<varchar,double>")],
+            vec![vec![
+                Value::from(1470000000),
+                Value::from(vec![(Value::from("x"), Value::from(1.5))]),
+            ]],
+        ));
+        let client = server.client(APIKEY);
+        let job_id = client
+            .issue_job(
+                QueryType::Presto,
+                "db0",
+                "select time, attrs",
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        client.wait_job(job_id, Some(0)).unwrap();
+        let schema = client.job(job_id).unwrap().result_schema().unwrap();
+
+        let chunk = RefCell::new(TableImportWritableChunk::new().unwrap());
+        client
+            .each_row_in_job_result(job_id, &|row: Vec<Value>| {
+                let mut chunk = chunk.borrow_mut();
+                chunk.next_row(row.len() as u32).unwrap();
+                for (column, value) in schema.columns.iter().zip(row.iter()) {
+                    chunk.write_key_and_value(&column.name, value).unwrap();
+                }
+                true
+            })
+            .unwrap();
+        let readable_chunk = chunk.into_inner().close().unwrap();
+        client
+            .import_msgpack_gz_file_to_table("db0", "tbl0", &readable_chunk.file_path, None)
+            .unwrap();
+
+        assert_eq!(
+            vec![Value::from(vec![
+                (Value::from("time"), Value::from(1470000000)),
+                (
+                    Value::from("attrs"),
+                    Value::from(vec![(Value::from("x"), Value::from(1.5))])
+                ),
+            ])],
+            server.table_rows("db0", "tbl0").unwrap()
+        );
+    }
+
+    #[test]
+    fn update_table() {
+        let server = FakeServer::start().unwrap();
+        server.create_database("db0");
+        let client = server.client(APIKEY);
+
+        client
+            .create_table_with(
+                "db0",
+                "tbl0",
+                &CreateTableOptions::new()
+                    .expire_days(30)
+                    .schema(&[("name", SchemaType::String)]),
+            )
+            .unwrap();
+        let table = client.tables("db0").unwrap().pop().unwrap();
+        assert_eq!(Some(30), table.expire_days);
+        assert_eq!(Some(true), table.include_v);
+        assert_eq!(r#"[["name","string"]]"#, table.schema);
+
+        let mut chunk = TableImportWritableChunk::new().unwrap();
+        chunk.next_row(2).unwrap();
+        chunk.write_key_and_i64("time", 1470000000).unwrap();
+        chunk.write_key_and_i64("count", 42).unwrap();
+        client
+            .import_msgpack_gz_file_to_table(
+                "db0",
+                "tbl0",
+                chunk.close().unwrap().file_path.as_str(),
+                None,
+            )
+            .unwrap();
+
+        client
+            .update_table(
+                "db0",
+                "tbl0",
+                &TableUpdate::new()
+                    .expire_days(7)
+                    .include_v(false)
+                    .detect_schema(true),
+            )
+            .unwrap();
+        let table = client.tables("db0").unwrap().pop().unwrap();
+        assert_eq!(Some(7), table.expire_days);
+        assert_eq!(Some(false), table.include_v);
+        assert_eq!(r#"[["count","long"]]"#, table.schema);
+
+        assert!(client
+            .update_table("db0", "tbl1", &TableUpdate::new().expire_days(7))
+            .is_err());
+    }
+
+    #[test]
+    fn jobs() {
+        let server = FakeServer::start().unwrap();
+        server.create_database("db0");
+        server.on_query(
+            "count(1)",
+            FakeJobResult::success(
+                &[("cnt", "bigint")],
+                vec![vec![Value::Integer(Integer::I64(42))]],
+            )
+            .running_polls(2),
+        );
+        server.on_query(
+            "broken",
+            FakeJobResult::error("line 1:8: Column 'broken' cannot be resolved"),
+        );
+        let client = server.client(APIKEY);
+
+        let job_id = client
+            .issue_job(
+                QueryType::Presto,
+                "db0",
+                "select count(1) as cnt from tbl0",
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        match client.job_status(job_id).unwrap() {
+            JobStatus::Running => (),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+        match client.wait_job(job_id, Some(0)).unwrap() {
+            JobStatus::Success => (),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+
+        let job = client.job(job_id).unwrap();
+        assert_eq!(
+            Some(vec![vec!["cnt".to_string(), "bigint".to_string()]]),
+            job.hive_result_schema
+        );
+
+        let rows = Arc::new(Mutex::new(vec![]));
+        client
+            .each_row_in_job_result(job_id, &|xs| {
+                rows.lock().unwrap().push(xs);
+                true
+            })
+            .unwrap();
+        assert_eq!(
+            vec![vec![Value::Integer(Integer::I64(42))]],
+            *rows.lock().unwrap()
+        );
+
+        let job_id = client
+            .issue_job(
+                QueryType::Hive,
+                "db0",
+                "select broken",
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        match client.wait_job(job_id, Some(0)).unwrap() {
+            JobStatus::Error => (),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+        assert_eq!(
+            Some("line 1:8: Column 'broken' cannot be resolved".to_string()),
+            client.job_debug(job_id).unwrap().error_message()
+        );
+
+        match client.query(
+            QueryType::Hive,
+            "db0",
+            "select broken",
+            &QueryOptions::new(),
+        ) {
+            Err(TreasureDataError::JobFailed(_, JobStatus::Error, ref debug)) => assert_eq!(
+                Some("line 1:8: Column 'broken' cannot be resolved".to_string()),
+                debug.error_message()
+            ),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("The query unexpectedly succeeded"),
+        }
+
+        let progress = Arc::new(Mutex::new(vec![]));
+        let result = {
+            let progress = progress.clone();
+            client
+                .query_with_progress(
+                    QueryType::Presto,
+                    "db0",
+                    "select count(1) as cnt from tbl0",
+                    &QueryOptions::new().wait_interval_secs(0),
+                    move |x: &TransferProgress| progress.lock().unwrap().push(*x),
+                )
+                .unwrap()
+        };
+        assert_eq!(
+            vec![ResultColumn {
+                name: "cnt".to_string(),
+                column_type: ColumnType::BigInt,
+            }],
+            result.schema.columns
+        );
+        assert_eq!(Some(1), result.job.num_records);
+        assert_eq!(
+            vec![vec![Value::Integer(Integer::I64(42))]],
+            result
+                .rows
+                .map(|row| row.unwrap())
+                .collect::<Vec<Vec<Value>>>()
+        );
+        let progress = progress.lock().unwrap();
+        assert_eq!(
+            vec![(Some(1), Some(1)), (Some(1), Some(1))],
+            progress
+                .iter()
+                .map(|x| (x.rows, x.total_rows))
+                .collect::<Vec<(Option<u64>, Option<u64>)>>()
+        );
+        assert!(progress[1].bytes > 0);
+        assert_eq!(Some(progress[1].bytes), progress[1].total_bytes);
+
+        let jobs = client.jobs(None, Some(2), None).unwrap();
+        assert_eq!(2, jobs.jobs.len());
+        assert_eq!(
+            vec!["select broken", "select count(1) as cnt from tbl0"],
+            jobs.jobs
+                .iter()
+                .map(|j| match j.query {
+                    JobQuery::Query(ref q) => q.as_str(),
+                    _ => "",
+                })
+                .collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn download_job_result_resumable() {
+        let server = FakeServer::start().unwrap();
+        server.create_database("db0");
+        server.default_job_result(FakeJobResult::success(
+            &[("n", "bigint")],
+            (0..100)
+                .map(|i| vec![Value::Integer(Integer::I64(i))])
+                .collect(),
+        ));
+        let client = server.client(APIKEY);
+        let job_id = client
+            .issue_job(
+                QueryType::Presto,
+                "db0",
+                "select n",
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        client.wait_job(job_id, Some(0)).unwrap();
+
+        let dir = TempDir::new("td-client-download").unwrap();
+        let full_path = dir.path().join("full.msgpack.gz");
+        client
+            .download_job_result(job_id, &File::create(&full_path).unwrap())
+            .unwrap();
+        let mut expected = vec![];
+        File::open(&full_path)
+            .unwrap()
+            .read_to_end(&mut expected)
+            .unwrap();
+
+        let progress = Mutex::new(None);
+        client
+            .each_row_in_job_result_with_progress(job_id, &|_| true, |x: &TransferProgress| {
+                *progress.lock().unwrap() = Some(*x)
+            })
+            .unwrap();
+        let progress = progress.into_inner().unwrap().unwrap();
+        assert_eq!(Some(100), progress.rows);
+        assert_eq!(Some(100), progress.total_rows);
+        assert_eq!(expected.len() as u64, progress.bytes);
+        assert_eq!(Some(expected.len() as u64), progress.total_bytes);
+
+        // The connection is dropped twice and the download resumes each time
+        server.interrupt_job_results(2, 10);
+        let path = dir.path().join("result.msgpack.gz");
+        let progress = Mutex::new(vec![]);
+        let len = client
+            .download_job_result_resumable(job_id, &path, 2, |x: &TransferProgress| {
+                progress.lock().unwrap().push((x.bytes, x.total_bytes))
+            })
+            .unwrap();
+        assert_eq!(expected.len() as u64, len);
+        let mut actual = vec![];
+        File::open(&path).unwrap().read_to_end(&mut actual).unwrap();
+        assert_eq!(expected, actual);
+        assert!(!dir.path().join("result.msgpack.gz.part").exists());
+        let progress = progress.into_inner().unwrap();
+        assert!(progress.contains(&(20, Some(len))));
+        assert_eq!(Some(&(len, Some(len))), progress.last());
+
+        // A later call resumes from the partial file
+        server.interrupt_job_results(1, 10);
+        let path = dir.path().join("retried.msgpack.gz");
+        assert!(client
+            .download_job_result_resumable(job_id, &path, 0, |_: &TransferProgress| ())
+            .is_err());
+        assert_eq!(
+            10,
+            fs::metadata(dir.path().join("retried.msgpack.gz.part"))
+                .unwrap()
+                .len()
+        );
+        client
+            .download_job_result_resumable(job_id, &path, 0, |_: &TransferProgress| ())
+            .unwrap();
+        let mut actual = vec![];
+        File::open(&path).unwrap().read_to_end(&mut actual).unwrap();
+        assert_eq!(expected, actual);
+
+        // A complete partial file is renamed without downloading it again
+        let path = dir.path().join("complete.msgpack.gz");
+        fs::write(dir.path().join("complete.msgpack.gz.part"), &expected).unwrap();
+        let progress = Mutex::new(vec![]);
+        client
+            .download_job_result_resumable(job_id, &path, 0, |x: &TransferProgress| {
+                progress.lock().unwrap().push((x.bytes, x.total_bytes))
+            })
+            .unwrap();
+        assert_eq!(expected, fs::read(&path).unwrap());
+        assert!(!dir.path().join("complete.msgpack.gz.part").exists());
+        let len = expected.len() as u64;
+        assert_eq!(vec![(len, Some(len))], progress.into_inner().unwrap());
+
+        // A partial file longer than the result is downloaded again
+        let path = dir.path().join("longer.msgpack.gz");
+        let mut longer = expected.clone();
+        longer.extend_from_slice(b"garbage");
+        fs::write(dir.path().join("longer.msgpack.gz.part"), &longer).unwrap();
+        client
+            .download_job_result_resumable(job_id, &path, 0, |_: &TransferProgress| ())
+            .unwrap();
+        assert_eq!(expected, fs::read(&path).unwrap());
+    }
+
+    #[test]
+    fn export_table() {
+        let server = FakeServer::start().unwrap();
+        server.create_table("db0", "tbl0");
+        let client = server.client(APIKEY);
+
+        let mut chunk = TableImportWritableChunk::new().unwrap();
+        for (time, name) in vec![(1500000000, "a"), (1500003600, "b"), (1500007200, "c")] {
+            chunk.next_row(2).unwrap();
+            chunk.write_key_and_i64("time", time).unwrap();
+            chunk.write_key_and_str("name", name).unwrap();
+        }
+        client
+            .import_msgpack_gz_file_to_table(
+                "db0",
+                "tbl0",
+                chunk.close().unwrap().file_path.as_str(),
+                None,
+            )
+            .unwrap();
+
+        let job = client
+            .export_table(
+                "db0",
+                "tbl0",
+                &TableExportParams::new("backup", "db0/tbl0/", "AKIA", "secret")
+                    .endpoint("localhost:9000")
+                    .format(ExportFormat::Tsv)
+                    .from(TimeStamp::from_unix_time(1500003600).unwrap())
+                    .to(TimeStamp::from_unix_time(1500007200).unwrap()),
+            )
+            .unwrap();
+        assert_eq!(JobType::Export, job.job_type);
+        match client.wait_job(job.job_id, Some(0)).unwrap() {
+            JobStatus::Success => (),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+
+        let objects = server.exported_objects();
+        assert_eq!(1, objects.len());
+        assert_eq!(Some("localhost:9000".to_string()), objects[0].endpoint);
+        assert_eq!("backup", objects[0].bucket);
+        assert_eq!("db0/tbl0/00000.tsv.gz", objects[0].key);
+        let mut content = String::new();
+        GzDecoder::new(&objects[0].body[..])
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!("1500003600\tb\n", content);
+
+        assert!(client
+            .export_table(
+                "db0",
+                "tbl1",
+                &TableExportParams::new("backup", "db0/tbl1/", "AKIA", "secret")
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn partial_delete() {
+        let server = FakeServer::start().unwrap();
+        server.create_table("db0", "tbl0");
+        let client = server.client(APIKEY);
+
+        let mut chunk = TableImportWritableChunk::new().unwrap();
+        for time in vec![1500000000, 1500003600, 1500007200] {
+            chunk.next_row(1).unwrap();
+            chunk.write_key_and_i64("time", time).unwrap();
+        }
+        client
+            .import_msgpack_gz_file_to_table(
+                "db0",
+                "tbl0",
+                chunk.close().unwrap().file_path.as_str(),
+                None,
+            )
+            .unwrap();
+        let tail = client
+            .tail_table(
+                "db0",
+                "tbl0",
+                Some(1),
+                None,
+                Some(TimeStamp::from_unix_time(1500007200).unwrap()),
+            )
+            .unwrap();
+        assert_eq!(
+            vec![Value::Map(vec![(
+                Value::String("time".to_string()),
+                Value::Integer(Integer::I64(1500003600))
+            )])],
+            tail
+        );
+
+        // 1500000000 isn't aligned to an hour
+        assert!(client
+            .partial_delete(
+                "db0",
+                "tbl0",
+                TimeStamp::from_unix_time(1500000000).unwrap(),
+                TimeStamp::from_unix_time(1500007200).unwrap(),
+                None,
+            )
+            .is_err());
+        assert!(client
+            .partial_delete(
+                "db0",
+                "tbl0",
+                TimeStamp::from_unix_time(1500004800).unwrap(),
+                TimeStamp::from_unix_time(1500001200).unwrap(),
+                None,
+            )
+            .is_err());
+
+        let job_id = client
+            .partial_delete(
+                "db0",
+                "tbl0",
+                TimeStamp::from_unix_time(1499997600).unwrap(),
+                TimeStamp::from_unix_time(1500004800).unwrap(),
+                Some("delete-0"),
+            )
+            .unwrap();
+        match client.wait_job(job_id, Some(0)).unwrap() {
+            JobStatus::Success => (),
+            status => panic!("Unexpected status: {:?}", status),
+        }
+        assert_eq!(JobType::PartialDelete, client.job(job_id).unwrap().job_type);
+
+        let rows = server.table_rows("db0", "tbl0").unwrap();
+        assert_eq!(1, rows.len());
+        assert_eq!(
+            Value::Map(vec![(
+                Value::String("time".to_string()),
+                Value::Integer(Integer::I64(1500007200))
+            )]),
+            rows[0]
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod fake_server;
+mod replay;
+
+pub use self::fake_server::*;
+pub use self::replay::*;
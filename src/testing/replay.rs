@@ -0,0 +1,542 @@
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Request, RequestBuilder, Response, StatusCode, Url};
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+use rustc_serialize::json::{Json, ToJson};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use client::{RequestExecutor, RANGE_RESPONSE_STATUSES};
+use error::*;
+
+const REDACTED: &'static str = "<REDACTED>";
+
+// Request/response pair stored in a fixture file
+#[derive(Debug, Clone)]
+pub struct RecordedExchange {
+    pub method: String,
+    pub path_and_query: String,
+    // `None` in fixtures recorded without request bodies, which match any body
+    pub request_body: Option<Vec<u8>>,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+fn invalid_fixture(path: &Path, reason: &str) -> TreasureDataError {
+    TreasureDataError::IoError(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid fixture {:?}: {}", path, reason),
+    ))
+}
+
+fn redact_body(body: Vec<u8>, apikey: &str) -> Vec<u8> {
+    match String::from_utf8(body) {
+        Ok(body) => body.replace(apikey, REDACTED).into_bytes(),
+        Err(err) => err.into_bytes(),
+    }
+}
+
+fn path_and_query(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+// reqwest 0.9 doesn't expose the content of a request body, so it's read back from
+// its `Debug` output like `Body { kind: b"{\"query\":\"select 1\"}" }`. Streamed
+// bodies like imported files are recorded as empty
+fn request_body(request: &Request) -> Vec<u8> {
+    let debug = match request.body() {
+        Some(body) => format!("{:?}", body),
+        None => return vec![],
+    };
+    let escaped = match (debug.find("b\""), debug.rfind('"')) {
+        (Some(start), Some(end)) if start + 1 < end => &debug.as_bytes()[start + 2..end],
+        _ => return vec![],
+    };
+    let mut body = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        match (escaped[i], escaped.get(i + 1)) {
+            (b'\\', Some(&b'n')) => body.push(b'\n'),
+            (b'\\', Some(&b'r')) => body.push(b'\r'),
+            (b'\\', Some(&b't')) => body.push(b'\t'),
+            (b'\\', Some(&b'0')) => body.push(0),
+            (b'\\', Some(&b'x')) if i + 3 < escaped.len() => {
+                let hex = ::std::str::from_utf8(&escaped[i + 2..i + 4]).unwrap_or("");
+                body.push(u8::from_str_radix(hex, 16).unwrap_or(0));
+                i += 2;
+            }
+            (b'\\', Some(&c)) => body.push(c),
+            (c, _) => {
+                body.push(c);
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+    body
+}
+
+// Keep text bodies readable so that fixtures can be reviewed and edited
+fn insert_body(json: &mut BTreeMap<String, Json>, key: &str, body: &[u8]) {
+    match String::from_utf8(body.to_vec()) {
+        Ok(body) => json.insert(key.to_string(), body.to_json()),
+        Err(_) => json.insert(
+            format!("{}_base64", key),
+            body.to_base64(STANDARD).to_json(),
+        ),
+    };
+}
+
+fn find_body(path: &Path, json: &Json, key: &str) -> Result<Option<Vec<u8>>, TreasureDataError> {
+    if let Some(body) = json.find(key).and_then(|x| x.as_string()) {
+        return Ok(Some(body.as_bytes().to_vec()));
+    }
+    let base64_key = format!("{}_base64", key);
+    match json.find(&base64_key) {
+        Some(body) => body
+            .as_string()
+            .and_then(|x| x.from_base64().ok())
+            .map(Some)
+            .ok_or_else(|| invalid_fixture(path, &base64_key)),
+        None => Ok(None),
+    }
+}
+
+impl RecordedExchange {
+    fn to_json(&self) -> Json {
+        let mut json = BTreeMap::new();
+        json.insert("method".to_string(), self.method.to_json());
+        json.insert("path_and_query".to_string(), self.path_and_query.to_json());
+        if let Some(ref request_body) = self.request_body {
+            insert_body(&mut json, "request_body", request_body);
+        }
+        json.insert("status".to_string(), (self.status as u64).to_json());
+        json.insert(
+            "headers".to_string(),
+            Json::Array(
+                self.headers
+                    .iter()
+                    .map(|&(ref k, ref v)| Json::Array(vec![k.to_json(), v.to_json()]))
+                    .collect(),
+            ),
+        );
+        insert_body(&mut json, "body", &self.body);
+        Json::Object(json)
+    }
+
+    fn from_json(path: &Path, json: &Json) -> Result<RecordedExchange, TreasureDataError> {
+        let string_item = |key: &str| {
+            json.find(key)
+                .and_then(|x| x.as_string())
+                .map(|x| x.to_string())
+                .ok_or_else(|| invalid_fixture(path, key))
+        };
+        let headers = json
+            .find("headers")
+            .and_then(|x| x.as_array())
+            .ok_or_else(|| invalid_fixture(path, "headers"))?
+            .iter()
+            .map(|header| match header.as_array() {
+                Some(kv) if kv.len() == 2 => match (kv[0].as_string(), kv[1].as_string()) {
+                    (Some(k), Some(v)) => Ok((k.to_string(), v.to_string())),
+                    _ => Err(invalid_fixture(path, "headers")),
+                },
+                _ => Err(invalid_fixture(path, "headers")),
+            })
+            .collect::<Result<Vec<(String, String)>, TreasureDataError>>()?;
+        let body = find_body(path, json, "body")?.ok_or_else(|| invalid_fixture(path, "body"))?;
+
+        Ok(RecordedExchange {
+            method: string_item("method")?,
+            path_and_query: string_item("path_and_query")?,
+            request_body: find_body(path, json, "request_body")?,
+            status: json
+                .find("status")
+                .and_then(|x| x.as_u64())
+                .ok_or_else(|| invalid_fixture(path, "status"))? as u16,
+            headers: headers,
+            body: body,
+        })
+    }
+
+    fn redact(&mut self, apikey: &str) {
+        if apikey.is_empty() {
+            return;
+        }
+        self.path_and_query = self.path_and_query.replace(apikey, REDACTED);
+        if let Some(request_body) = self.request_body.take() {
+            self.request_body = Some(redact_body(request_body, apikey));
+        }
+        for header in self.headers.iter_mut() {
+            header.1 = header.1.replace(apikey, REDACTED);
+        }
+        self.body = redact_body(self.body.clone(), apikey);
+    }
+
+    fn to_response(&self, statuses: &[StatusCode]) -> Result<Response, TreasureDataError> {
+        let mut builder = ::http::Response::builder();
+        builder.status(self.status);
+        for &(ref k, ref v) in &self.headers {
+            builder.header(k.as_str(), v.as_str());
+        }
+        let response = builder.body(self.body.clone()).map_err(|err| {
+            TreasureDataError::IoError(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to build a response: {}", err),
+            ))
+        })?;
+        let mut res = Response::from(response);
+
+        if statuses.contains(&res.status()) {
+            Ok(res)
+        } else {
+            Err(TreasureDataError::ApiError(res.status(), res.text()?))
+        }
+    }
+}
+
+// `RequestExecutor` which sends requests to the actual API like `DefaultRequestExecutor`
+// and saves each request/response pair into `fixture_dir` with the API key redacted
+pub struct RecordingRequestExecutor {
+    apikey: String,
+    fixture_dir: PathBuf,
+    http_client: ::reqwest::Client,
+    seq: Mutex<u32>,
+}
+
+impl RecordingRequestExecutor {
+    // Fixtures are numbered after the ones which already exist in `fixture_dir`
+    pub fn new(apikey: &str, fixture_dir: &Path) -> Result<Self, TreasureDataError> {
+        fs::create_dir_all(fixture_dir)?;
+        let mut seq = 0;
+        for entry in fs::read_dir(fixture_dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == "json") {
+                if let Some(x) = path
+                    .file_stem()
+                    .and_then(|x| x.to_str())
+                    .and_then(|x| x.parse::<u32>().ok())
+                {
+                    seq = seq.max(x);
+                }
+            }
+        }
+        Ok(RecordingRequestExecutor {
+            apikey: apikey.to_string(),
+            fixture_dir: fixture_dir.to_path_buf(),
+            http_client: ::reqwest::Client::new(),
+            seq: Mutex::new(seq),
+        })
+    }
+
+    fn save(&self, exchange: &RecordedExchange) -> Result<(), TreasureDataError> {
+        let mut seq = self.seq.lock().unwrap();
+        *seq += 1;
+        let mut file = File::create(self.fixture_dir.join(format!("{:04}.json", *seq)))?;
+        file.write_all(exchange.to_json().pretty().to_string().as_bytes())?;
+        Ok(())
+    }
+}
+
+impl RecordingRequestExecutor {
+    fn record(
+        &self,
+        request_builder: RequestBuilder,
+        statuses: &[StatusCode],
+    ) -> Result<Response, TreasureDataError> {
+        let request = request_builder
+            .header(
+                AUTHORIZATION,
+                format!("TD1 {}", self.apikey).as_str().to_owned(),
+            )
+            .build()?;
+        let method = request.method().to_string();
+        let path_and_query = path_and_query(request.url());
+        let request_body = request_body(&request);
+
+        let mut res = self.http_client.execute(request)?;
+        let mut body = vec![];
+        res.read_to_end(&mut body)?;
+
+        let mut exchange = RecordedExchange {
+            method: method,
+            path_and_query: path_and_query,
+            request_body: Some(request_body),
+            status: res.status().as_u16(),
+            headers: res
+                .headers()
+                .iter()
+                .filter_map(|(k, v)| {
+                    v.to_str()
+                        .ok()
+                        .map(|v| (k.as_str().to_string(), v.to_string()))
+                })
+                .collect(),
+            body: body,
+        };
+        exchange.redact(&self.apikey);
+        self.save(&exchange)?;
+        exchange.to_response(statuses)
+    }
+}
+
+impl RequestExecutor for RecordingRequestExecutor {
+    fn get_response(&self, request_builder: RequestBuilder) -> Result<Response, TreasureDataError> {
+        self.record(request_builder, &[StatusCode::OK])
+    }
+
+    fn get_range_response(
+        &self,
+        request_builder: RequestBuilder,
+    ) -> Result<Response, TreasureDataError> {
+        self.record(request_builder, RANGE_RESPONSE_STATUSES)
+    }
+}
+
+// `RequestExecutor` which serves responses saved by `RecordingRequestExecutor`
+// without accessing the network.
+// A request is answered with the first unused exchange which has the same method,
+// path, query and body. The host of the endpoint is ignored
+pub struct ReplayRequestExecutor {
+    exchanges: Mutex<Vec<(RecordedExchange, bool)>>,
+}
+
+impl ReplayRequestExecutor {
+    pub fn new(fixture_dir: &Path) -> Result<Self, TreasureDataError> {
+        let mut paths = fs::read_dir(fixture_dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<PathBuf>, io::Error>>()?;
+        paths.retain(|path| path.extension().map_or(false, |ext| ext == "json"));
+        paths.sort();
+
+        let mut exchanges = vec![];
+        for path in paths {
+            let mut content = String::new();
+            File::open(&path)?.read_to_string(&mut content)?;
+            let json = Json::from_str(&content)?;
+            exchanges.push(RecordedExchange::from_json(&path, &json)?);
+        }
+        Ok(ReplayRequestExecutor::from_exchanges(exchanges))
+    }
+
+    pub fn from_exchanges(exchanges: Vec<RecordedExchange>) -> Self {
+        ReplayRequestExecutor {
+            exchanges: Mutex::new(exchanges.into_iter().map(|x| (x, false)).collect()),
+        }
+    }
+
+    // Returns exchanges which haven't been served yet
+    pub fn unused_exchanges(&self) -> Vec<RecordedExchange> {
+        let exchanges = self.exchanges.lock().unwrap();
+        exchanges
+            .iter()
+            .filter(|&&(_, used)| !used)
+            .map(|&(ref x, _)| x.clone())
+            .collect()
+    }
+}
+
+impl ReplayRequestExecutor {
+    fn replay(
+        &self,
+        request_builder: RequestBuilder,
+        statuses: &[StatusCode],
+    ) -> Result<Response, TreasureDataError> {
+        let request = request_builder.build()?;
+        let method = request.method().to_string();
+        let path_and_query = path_and_query(request.url());
+        let request_body = request_body(&request);
+
+        let mut exchanges = self.exchanges.lock().unwrap();
+        match exchanges.iter_mut().find(|&&mut (ref x, used)| {
+            !used
+                && x.method == method
+                && x.path_and_query == path_and_query
+                && x.request_body.as_ref().map_or(true, |x| *x == request_body)
+        }) {
+            Some(&mut (ref x, ref mut used)) => {
+                *used = true;
+                x.to_response(statuses)
+            }
+            None => Err(TreasureDataError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No recorded response for {} {}", method, path_and_query),
+            ))),
+        }
+    }
+}
+
+impl RequestExecutor for ReplayRequestExecutor {
+    fn get_response(&self, request_builder: RequestBuilder) -> Result<Response, TreasureDataError> {
+        self.replay(request_builder, &[StatusCode::OK])
+    }
+
+    fn get_range_response(
+        &self,
+        request_builder: RequestBuilder,
+    ) -> Result<Response, TreasureDataError> {
+        self.replay(request_builder, RANGE_RESPONSE_STATUSES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate mockito;
+    use self::mockito::{mock, Matcher};
+
+    use client::{Client, DefaultRequestExecutor, RequestExecutor};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use model::QueryType;
+    use progress::TransferProgress;
+    use rmp::encode::*;
+    use std::sync::Mutex;
+    use tempdir::TempDir;
+    use testing::*;
+
+    const APIKEY: &'static str = "1234abcd";
+
+    #[test]
+    fn record_and_replay() {
+        let fixture_dir = TempDir::new("td-client-rust-fixtures").unwrap();
+
+        {
+            let _mock_endpoint = mock("GET", "/v3/database/list")
+                .match_header("Authorization", "TD1 1234abcd")
+                .with_status(200)
+                .with_header("Content-Type", "application/json")
+                .with_body(
+                    r#"{"databases":[
+                          {"name":"db0", "count":42, "created_at":"2016-01-01 00:00:00 UTC",
+                           "updated_at":"2016-01-01 01:01:01 UTC", "permission":"query_only",
+                           "note":"1234abcd"}
+                          ]}"#,
+                )
+                .create();
+
+            let mut client = Client::<DefaultRequestExecutor>::new_with_request_executor(
+                APIKEY,
+                RecordingRequestExecutor::new(APIKEY, fixture_dir.path()).unwrap(),
+            );
+            client.endpoint(&mockito::server_url());
+            assert_eq!(1, client.databases().unwrap().len());
+        }
+
+        let replay = ReplayRequestExecutor::new(fixture_dir.path()).unwrap();
+        let recorded = replay.unused_exchanges();
+        assert_eq!(1, recorded.len());
+        assert_eq!("/v3/database/list", recorded[0].path_and_query);
+        let body = String::from_utf8(recorded[0].body.clone()).unwrap();
+        assert!(!body.contains(APIKEY));
+        assert!(body.contains("<REDACTED>"));
+
+        let mut client =
+            Client::<DefaultRequestExecutor>::new_with_request_executor("another API key", replay);
+        client.endpoint("https://api.example.com");
+        let databases = client.databases().unwrap();
+        assert_eq!("db0", databases[0].name);
+        assert_eq!(42, databases[0].count);
+
+        // Each exchange is served only once
+        assert!(client.databases().is_err());
+    }
+
+    fn issue_job<R: RequestExecutor>(client: &Client<R>, query: &str) -> u64 {
+        client
+            .issue_job(
+                QueryType::Presto,
+                "db0",
+                query,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn replay_by_request_body() {
+        let fixture_dir = TempDir::new("td-client-rust-fixtures").unwrap();
+
+        for &(query, job_id) in [("select 1", "1"), ("select 2", "2")].iter() {
+            let _mock_endpoint = mock("POST", "/v3/job/issue/presto/db0")
+                .match_body(Matcher::JsonString(format!(r#"{{"query":"{}"}}"#, query)))
+                .with_status(200)
+                .with_body(format!(r#"{{"job_id":"{}"}}"#, job_id))
+                .create();
+
+            // Each recorder continues after the fixtures which already exist
+            let mut client = Client::<DefaultRequestExecutor>::new_with_request_executor(
+                APIKEY,
+                RecordingRequestExecutor::new(APIKEY, fixture_dir.path()).unwrap(),
+            );
+            client.endpoint(&mockito::server_url());
+            issue_job(&client, query);
+        }
+        assert!(fixture_dir.path().join("0001.json").exists());
+        assert!(fixture_dir.path().join("0002.json").exists());
+
+        let replay = ReplayRequestExecutor::new(fixture_dir.path()).unwrap();
+        let client = Client::<DefaultRequestExecutor>::new_with_request_executor(APIKEY, replay);
+        assert_eq!(2, issue_job(&client, "select 2"));
+        assert_eq!(1, issue_job(&client, "select 1"));
+    }
+
+    fn job_result_exchange(rows: &[(i64, &str)]) -> RecordedExchange {
+        let mut write = GzEncoder::new(vec![], Compression::default());
+        for &(x, y) in rows {
+            write_array_len(&mut write, 2).unwrap();
+            write_sint(&mut write, x).unwrap();
+            write_str(&mut write, y).unwrap();
+        }
+        let body = write.finish().unwrap();
+        RecordedExchange {
+            method: "GET".to_string(),
+            path_and_query: "/v3/job/result/12345?format=msgpack_gz".to_string(),
+            request_body: None,
+            status: 200,
+            headers: vec![("content-length".to_string(), body.len().to_string())],
+            body: body,
+        }
+    }
+
+    #[test]
+    fn each_row_in_job_result_requests() {
+        // Only the result is requested
+        let replay =
+            ReplayRequestExecutor::from_exchanges(vec![job_result_exchange(&[(1, "a"), (2, "b")])]);
+        let client = Client::<DefaultRequestExecutor>::new_with_request_executor(APIKEY, replay);
+        let rows = Mutex::new(vec![]);
+        client
+            .each_row_in_job_result(12345, &|row| {
+                rows.lock().unwrap().push(row);
+                true
+            })
+            .unwrap();
+        assert_eq!(2, rows.into_inner().unwrap().len());
+
+        // The result is read even when the job can't be looked up for `total_rows`
+        let replay = ReplayRequestExecutor::from_exchanges(vec![job_result_exchange(&[(1, "a")])]);
+        let client = Client::<DefaultRequestExecutor>::new_with_request_executor(APIKEY, replay);
+        let progress = Mutex::new(None);
+        client
+            .each_row_in_job_result_with_progress(12345, &|_| true, |x: &TransferProgress| {
+                *progress.lock().unwrap() = Some(*x)
+            })
+            .unwrap();
+        let progress = progress.into_inner().unwrap().unwrap();
+        assert_eq!(Some(1), progress.rows);
+        assert_eq!(None, progress.total_rows);
+    }
+}
@@ -1,3 +1,15 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use rmp::encode::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+
+use error::InvalidArgument;
+use model::TimeStamp;
+
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub enum Integer {
     U64(u64),
@@ -51,3 +63,914 @@ impl From<::rmpv::Value> for Value {
         }
     }
 }
+
+impl From<Value> for ::rmpv::Value {
+    fn from(src: Value) -> ::rmpv::Value {
+        match src {
+            Value::Nil => ::rmpv::Value::Nil,
+            Value::Boolean(x) => ::rmpv::Value::Boolean(x),
+            Value::Integer(Integer::I64(x)) => ::rmpv::Value::from(x),
+            Value::Integer(Integer::U64(x)) => ::rmpv::Value::from(x),
+            Value::Float(Float::F32(x)) => ::rmpv::Value::F32(x),
+            Value::Float(Float::F64(x)) => ::rmpv::Value::F64(x),
+            Value::String(x) => ::rmpv::Value::from(x),
+            Value::Binary(x) => ::rmpv::Value::Binary(x),
+            Value::Array(xs) => {
+                ::rmpv::Value::Array(xs.into_iter().map(|x| ::rmpv::Value::from(x)).collect())
+            }
+            Value::Map(xs) => ::rmpv::Value::Map(
+                xs.into_iter()
+                    .map(|(k, v)| (::rmpv::Value::from(k), ::rmpv::Value::from(v)))
+                    .collect(),
+            ),
+            Value::Ext(i, x) => ::rmpv::Value::Ext(i, x),
+        }
+    }
+}
+
+impl Value {
+    // Writes this value in MessagePack like `rmpv::encode::write_value` without
+    // converting it into `rmpv::Value`
+    pub fn write_msgpack<W>(&self, wr: &mut W) -> Result<(), ValueWriteError>
+    where
+        W: ::std::io::Write,
+    {
+        match *self {
+            Value::Nil => write_nil(wr).map_err(ValueWriteError::InvalidMarkerWrite)?,
+            Value::Boolean(x) => write_bool(wr, x).map_err(ValueWriteError::InvalidMarkerWrite)?,
+            Value::Integer(Integer::I64(x)) => {
+                write_sint(wr, x)?;
+            }
+            Value::Integer(Integer::U64(x)) => {
+                write_uint(wr, x)?;
+            }
+            Value::Float(Float::F32(x)) => write_f32(wr, x)?,
+            Value::Float(Float::F64(x)) => write_f64(wr, x)?,
+            Value::String(ref x) => write_str(wr, x)?,
+            Value::Binary(ref x) => write_bin(wr, x)?,
+            Value::Array(ref xs) => {
+                write_array_len(wr, xs.len() as u32)?;
+                for x in xs {
+                    x.write_msgpack(wr)?;
+                }
+            }
+            Value::Map(ref kvs) => {
+                write_map_len(wr, kvs.len() as u32)?;
+                for &(ref k, ref v) in kvs {
+                    k.write_msgpack(wr)?;
+                    v.write_msgpack(wr)?;
+                }
+            }
+            Value::Ext(i, ref x) => {
+                write_ext_meta(wr, x.len() as u32, i)?;
+                wr.write_all(x).map_err(ValueWriteError::InvalidDataWrite)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<::rustc_serialize::json::Json> for Value {
+    fn from(src: ::rustc_serialize::json::Json) -> Value {
+        use rustc_serialize::json::Json;
+        match src {
+            Json::Null => Value::Nil,
+            Json::Boolean(x) => Value::Boolean(x),
+            Json::I64(x) => Value::Integer(Integer::I64(x)),
+            Json::U64(x) => Value::Integer(Integer::U64(x)),
+            Json::F64(x) => Value::Float(Float::F64(x)),
+            Json::String(x) => Value::String(x),
+            Json::Array(xs) => Value::Array(xs.into_iter().map(|x| Value::from(x)).collect()),
+            Json::Object(xs) => Value::Map(
+                xs.into_iter()
+                    .map(|(k, v)| (Value::String(k), Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Value {
+    pub fn is_nil(&self) -> bool {
+        *self == Value::Nil
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    // Returns `None` for a `U64` larger than `i64::max_value()`
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(Integer::I64(x)) => Some(x),
+            Value::Integer(Integer::U64(x)) if x <= i64::max_value() as u64 => Some(x as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Integer(Integer::U64(x)) => Some(x),
+            Value::Integer(Integer::I64(x)) if x >= 0 => Some(x as u64),
+            _ => None,
+        }
+    }
+
+    // Integers are also converted
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(Float::F32(x)) => Some(x as f64),
+            Value::Float(Float::F64(x)) => Some(x),
+            Value::Integer(Integer::I64(x)) => Some(x as f64),
+            Value::Integer(Integer::U64(x)) => Some(x as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref x) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Binary(ref x) => Some(x.as_slice()),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match *self {
+            Value::Array(ref xs) => Some(xs),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&Vec<(Value, Value)>> {
+        match *self {
+            Value::Map(ref xs) => Some(xs),
+            _ => None,
+        }
+    }
+
+    // Looks up a map by a string key like a record from `Client::tail_table`
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.as_map().and_then(|xs| {
+            xs.iter()
+                .find(|&&(ref k, _)| k.as_str() == Some(key))
+                .map(|&(_, ref v)| v)
+        })
+    }
+}
+
+fn conversion_error(type_name: &str, value: &Value) -> InvalidArgument {
+    InvalidArgument {
+        key: type_name.to_string(),
+        value: format!("{:?}", value),
+    }
+}
+
+// `TryFrom<&Value>` for `$t` and `Option<$t>`, which is `None` for `Nil`
+macro_rules! impl_try_from_value {
+    ($t:ty, $type_name:expr, $value:ident => $conv:expr) => {
+        impl<'a> TryFrom<&'a Value> for $t {
+            type Error = InvalidArgument;
+
+            fn try_from($value: &'a Value) -> Result<Self, Self::Error> {
+                $conv.ok_or_else(|| conversion_error($type_name, $value))
+            }
+        }
+
+        impl<'a> TryFrom<&'a Value> for Option<$t> {
+            type Error = InvalidArgument;
+
+            fn try_from(value: &'a Value) -> Result<Self, Self::Error> {
+                match *value {
+                    Value::Nil => Ok(None),
+                    _ => <$t>::try_from(value).map(Some),
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_integer_conversions {
+    ($($t:ident),*) => {
+        $(
+            impl_try_from_value!($t, stringify!($t), value => match *value {
+                Value::Integer(Integer::I64(x)) => $t::try_from(x).ok(),
+                Value::Integer(Integer::U64(x)) => $t::try_from(x).ok(),
+                _ => None,
+            });
+
+            impl From<$t> for Value {
+                fn from(x: $t) -> Value {
+                    match i64::try_from(x) {
+                        Ok(x) => Value::Integer(Integer::I64(x)),
+                        Err(_) => Value::Integer(Integer::U64(x as u64)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_conversions!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl_try_from_value!(f64, "f64", value => value.as_f64());
+impl_try_from_value!(f32, "f32", value => value.as_f64().map(|x| x as f32));
+impl_try_from_value!(bool, "bool", value => value.as_bool());
+impl_try_from_value!(String, "String", value => value.as_str().map(|x| x.to_string()));
+impl_try_from_value!(Vec<u8>, "Vec<u8>", value => value.as_bytes().map(|x| x.to_vec()));
+
+// From UNIX time or a string like "2016-01-01 00:00:00 UTC" or RFC 3339
+impl_try_from_value!(DateTime<Utc>, "DateTime<Utc>", value => match *value {
+    Value::String(ref x) => x
+        .parse::<DateTime<Utc>>()
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(x, "%Y-%m-%d %H:%M:%S UTC")
+                .ok()
+                .map(|x| Utc.from_utc_datetime(&x))
+        }),
+    _ => value.as_i64().and_then(|x| Utc.timestamp_opt(x, 0).single()),
+});
+impl_try_from_value!(TimeStamp, "TimeStamp", value => DateTime::<Utc>::try_from(value)
+    .ok()
+    .and_then(|x| TimeStamp::from_unix_time(x.timestamp())));
+
+impl From<f32> for Value {
+    fn from(x: f32) -> Value {
+        Value::Float(Float::F32(x))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Value {
+        Value::Float(Float::F64(x))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(x: bool) -> Value {
+        Value::Boolean(x)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(x: &'a str) -> Value {
+        Value::String(x.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(x: String) -> Value {
+        Value::String(x)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Value {
+    fn from(x: &'a [u8]) -> Value {
+        Value::Binary(x.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(x: Vec<u8>) -> Value {
+        Value::Binary(x)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(xs: Vec<Value>) -> Value {
+        Value::Array(xs)
+    }
+}
+
+impl From<Vec<(Value, Value)>> for Value {
+    fn from(xs: Vec<(Value, Value)>) -> Value {
+        Value::Map(xs)
+    }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+    fn from(xs: BTreeMap<String, Value>) -> Value {
+        Value::Map(xs.into_iter().map(|(k, v)| (Value::String(k), v)).collect())
+    }
+}
+
+impl<T> From<Option<T>> for Value
+where
+    T: Into<Value>,
+{
+    fn from(x: Option<T>) -> Value {
+        match x {
+            Some(x) => x.into(),
+            None => Value::Nil,
+        }
+    }
+}
+
+// UNIX time as the `time` column of a table
+impl From<DateTime<Utc>> for Value {
+    fn from(x: DateTime<Utc>) -> Value {
+        Value::Integer(Integer::I64(x.timestamp()))
+    }
+}
+
+impl From<TimeStamp> for Value {
+    fn from(x: TimeStamp) -> Value {
+        Value::Integer(Integer::I64(x.unix_time()))
+    }
+}
+
+#[cfg(feature = "serde_json")]
+impl From<::serde_json::Value> for Value {
+    fn from(src: ::serde_json::Value) -> Value {
+        match src {
+            ::serde_json::Value::Null => Value::Nil,
+            ::serde_json::Value::Bool(x) => Value::Boolean(x),
+            ::serde_json::Value::Number(x) => match (x.as_i64(), x.as_u64()) {
+                (Some(x), _) => Value::Integer(Integer::I64(x)),
+                (_, Some(x)) => Value::Integer(Integer::U64(x)),
+                _ => Value::Float(Float::F64(x.as_f64().unwrap_or(::std::f64::NAN))),
+            },
+            ::serde_json::Value::String(x) => Value::String(x),
+            ::serde_json::Value::Array(xs) => {
+                Value::Array(xs.into_iter().map(|x| Value::from(x)).collect())
+            }
+            ::serde_json::Value::Object(xs) => Value::Map(
+                xs.into_iter()
+                    .map(|(k, v)| (Value::String(k), Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// Binaries become arrays of bytes, non-finite floats become null and non-string
+// map keys are rendered as JSON text
+#[cfg(feature = "serde_json")]
+impl From<Value> for ::serde_json::Value {
+    fn from(src: Value) -> ::serde_json::Value {
+        match src {
+            Value::Nil => ::serde_json::Value::Null,
+            Value::Boolean(x) => ::serde_json::Value::Bool(x),
+            Value::Integer(Integer::I64(x)) => ::serde_json::Value::from(x),
+            Value::Integer(Integer::U64(x)) => ::serde_json::Value::from(x),
+            Value::Float(Float::F32(x)) => ::serde_json::Value::from(x as f64),
+            Value::Float(Float::F64(x)) => ::serde_json::Value::from(x),
+            Value::String(x) => ::serde_json::Value::String(x),
+            Value::Binary(x) => ::serde_json::Value::from(x),
+            Value::Array(xs) => ::serde_json::Value::Array(
+                xs.into_iter()
+                    .map(|x| ::serde_json::Value::from(x))
+                    .collect(),
+            ),
+            Value::Map(xs) => ::serde_json::Value::Object(
+                xs.into_iter()
+                    .map(|(k, v)| {
+                        let k = match k {
+                            Value::String(k) => k,
+                            k => ::serde_json::Value::from(k).to_string(),
+                        };
+                        (k, ::serde_json::Value::from(v))
+                    })
+                    .collect(),
+            ),
+            Value::Ext(i, x) => ::serde_json::Value::Array(vec![
+                ::serde_json::Value::from(i),
+                ::serde_json::Value::from(x),
+            ]),
+        }
+    }
+}
+
+fn write_json_string(f: &mut dyn fmt::Write, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+// f32 is written as it is so that `0.1f32` is `0.1`, not `0.10000000149011612`
+fn write_float<T: Into<f64> + fmt::Debug + Copy>(f: &mut dyn fmt::Write, x: T) -> fmt::Result {
+    let y: f64 = x.into();
+    if y.is_nan() {
+        f.write_str("NaN")
+    } else if y.is_infinite() {
+        f.write_str(if y > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        write!(f, "{:?}", x)
+    }
+}
+
+fn write_hex(f: &mut dyn fmt::Write, xs: &[u8]) -> fmt::Result {
+    f.write_str("0x")?;
+    for x in xs {
+        write!(f, "{:02x}", x)?;
+    }
+    Ok(())
+}
+
+// JSON-like text. Floats keep a fractional part like `1.0`, non-finite floats are
+// `NaN`, `Infinity` or `-Infinity` and binaries are hexadecimal like `0x01ff`
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Nil => f.write_str("null"),
+            Value::Boolean(x) => write!(f, "{}", x),
+            Value::Integer(Integer::I64(x)) => write!(f, "{}", x),
+            Value::Integer(Integer::U64(x)) => write!(f, "{}", x),
+            Value::Float(Float::F32(x)) => write_float(f, x),
+            Value::Float(Float::F64(x)) => write_float(f, x),
+            Value::String(ref x) => write_json_string(f, x),
+            Value::Binary(ref x) => write_hex(f, x),
+            Value::Array(ref xs) => {
+                f.write_str("[")?;
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", x)?;
+                }
+                f.write_str("]")
+            }
+            Value::Map(ref kvs) => {
+                f.write_str("{")?;
+                for (i, &(ref k, ref v)) in kvs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}:{}", k, v)?;
+                }
+                f.write_str("}")
+            }
+            Value::Ext(i, ref x) => {
+                write!(f, "ext({},", i)?;
+                write_hex(f, x)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl Value {
+    // JSON text which is the same for the same data: map entries are sorted by key,
+    // there is no whitespace, floats are written in the shortest form (`1.0` as `1`),
+    // non-finite floats are `null`, binaries are arrays of bytes, extensions are
+    // `[type, bytes]` and non-string map keys are their canonical JSON text
+    pub fn to_canonical_json(&self) -> String {
+        let mut out = String::new();
+        self.write_canonical_json(&mut out);
+        out
+    }
+
+    fn write_canonical_json(&self, out: &mut String) {
+        match *self {
+            Value::Float(Float::F32(x)) => write_canonical_float(out, x),
+            Value::Float(Float::F64(x)) => write_canonical_float(out, x),
+            Value::Binary(ref x) => {
+                let xs = x.iter().map(|x| Value::from(*x)).collect();
+                Value::Array(xs).write_canonical_json(out)
+            }
+            Value::Ext(i, ref x) => {
+                let xs = x.iter().map(|x| Value::from(*x)).collect();
+                Value::Array(vec![Value::from(i), Value::Array(xs)]).write_canonical_json(out)
+            }
+            Value::Array(ref xs) => {
+                out.push('[');
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    x.write_canonical_json(out);
+                }
+                out.push(']');
+            }
+            Value::Map(ref kvs) => {
+                let mut entries: Vec<(String, &Value)> = kvs
+                    .iter()
+                    .map(|&(ref k, ref v)| match *k {
+                        Value::String(ref k) => (k.clone(), v),
+                        ref k => (k.to_canonical_json(), v),
+                    })
+                    .collect();
+                entries.sort_by(|a, b| a.0.cmp(&b.0));
+                out.push('{');
+                for (i, (k, v)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_string(out, &k).unwrap();
+                    out.push(':');
+                    v.write_canonical_json(out);
+                }
+                out.push('}');
+            }
+            // Others are the same as `Display`
+            ref x => write!(out, "{}", x).unwrap(),
+        }
+    }
+}
+
+fn write_canonical_float<T: Into<f64> + fmt::Display + Copy>(out: &mut String, x: T) {
+    let y: f64 = x.into();
+    if y == 0.0 {
+        out.push('0')
+    } else if y.is_finite() {
+        // `Display` of floats is the shortest form without an exponent
+        write!(out, "{}", x).unwrap()
+    } else {
+        out.push_str("null")
+    }
+}
+
+// Canonical JSON array of a row of a job result, which can be used as a key to dedupe rows
+pub fn row_to_canonical_json(row: &[Value]) -> String {
+    let mut out = String::new();
+    Value::Array(row.to_vec()).write_canonical_json(&mut out);
+    out
+}
+
+// `Value` with a total order, `Eq` and `Hash`, e.g. to be a key of `HashSet` or `BTreeMap`.
+// Values are ordered by kind first: nil, boolean, integer, float, string, binary, array,
+// map and extension. Integers and floats are compared by their numeric values within each
+// kind, so `F32(1.5)` equals `F64(1.5)`. All NaNs are equal and greater than any other
+// float, and `-0.0` equals `0.0`. Maps are compared by their entries sorted by key
+#[derive(Clone, Debug)]
+pub struct OrderedValue(pub Value);
+
+impl OrderedValue {
+    pub fn into_inner(self) -> Value {
+        self.0
+    }
+}
+
+impl From<Value> for OrderedValue {
+    fn from(x: Value) -> OrderedValue {
+        OrderedValue(x)
+    }
+}
+
+fn kind_rank(value: &Value) -> u8 {
+    match *value {
+        Value::Nil => 0,
+        Value::Boolean(_) => 1,
+        Value::Integer(_) => 2,
+        Value::Float(_) => 3,
+        Value::String(_) => 4,
+        Value::Binary(_) => 5,
+        Value::Array(_) => 6,
+        Value::Map(_) => 7,
+        Value::Ext(..) => 8,
+    }
+}
+
+fn integer_key(x: &Integer) -> i128 {
+    match *x {
+        Integer::I64(x) => x as i128,
+        Integer::U64(x) => x as i128,
+    }
+}
+
+// NaNs and zeros are normalized so that equal floats have the same bits
+fn float_key(x: &Float) -> f64 {
+    let x = match *x {
+        Float::F32(x) => x as f64,
+        Float::F64(x) => x,
+    };
+    if x.is_nan() {
+        ::std::f64::NAN
+    } else if x == 0.0 {
+        0.0
+    } else {
+        x
+    }
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (&Value::Boolean(a), &Value::Boolean(b)) => a.cmp(&b),
+        (&Value::Integer(ref a), &Value::Integer(ref b)) => integer_key(a).cmp(&integer_key(b)),
+        (&Value::Float(ref a), &Value::Float(ref b)) => {
+            let (a, b) = (float_key(a), float_key(b));
+            match (a.is_nan(), b.is_nan()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => a.partial_cmp(&b).unwrap(),
+            }
+        }
+        (&Value::String(ref a), &Value::String(ref b)) => a.cmp(b),
+        (&Value::Binary(ref a), &Value::Binary(ref b)) => a.cmp(b),
+        (&Value::Array(ref a), &Value::Array(ref b)) => compare_seqs(a.iter(), b.iter()),
+        (&Value::Map(ref a), &Value::Map(ref b)) => compare_seqs(
+            sorted_entries(a)
+                .into_iter()
+                .flat_map(|&(ref k, ref v)| vec![k, v]),
+            sorted_entries(b)
+                .into_iter()
+                .flat_map(|&(ref k, ref v)| vec![k, v]),
+        ),
+        (&Value::Ext(ai, ref a), &Value::Ext(bi, ref b)) => (ai, a).cmp(&(bi, b)),
+        (a, b) => kind_rank(a).cmp(&kind_rank(b)),
+    }
+}
+
+// Map entries are compared and hashed in the order of their keys like `to_canonical_json`,
+// so that `{"a":1,"b":2}` equals `{"b":2,"a":1}`
+fn sorted_entries(kvs: &[(Value, Value)]) -> Vec<&(Value, Value)> {
+    let mut entries: Vec<&(Value, Value)> = kvs.iter().collect();
+    entries.sort_by(|a, b| compare_values(&a.0, &b.0).then_with(|| compare_values(&a.1, &b.1)));
+    entries
+}
+
+fn compare_seqs<'a, I, J>(mut a: I, mut b: J) -> Ordering
+where
+    I: Iterator<Item = &'a Value>,
+    J: Iterator<Item = &'a Value>,
+{
+    loop {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) => match compare_values(x, y) {
+                Ordering::Equal => (),
+                ordering => return ordering,
+            },
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (None, None) => return Ordering::Equal,
+        }
+    }
+}
+
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    kind_rank(value).hash(state);
+    match *value {
+        Value::Nil => (),
+        Value::Boolean(x) => x.hash(state),
+        Value::Integer(ref x) => integer_key(x).hash(state),
+        Value::Float(ref x) => float_key(x).to_bits().hash(state),
+        Value::String(ref x) => x.hash(state),
+        Value::Binary(ref x) => x.hash(state),
+        Value::Array(ref xs) => {
+            xs.len().hash(state);
+            for x in xs {
+                hash_value(x, state);
+            }
+        }
+        Value::Map(ref kvs) => {
+            kvs.len().hash(state);
+            for &(ref k, ref v) in sorted_entries(kvs) {
+                hash_value(k, state);
+                hash_value(v, state);
+            }
+        }
+        Value::Ext(i, ref x) => {
+            i.hash(state);
+            x.hash(state);
+        }
+    }
+}
+
+impl PartialEq for OrderedValue {
+    fn eq(&self, other: &OrderedValue) -> bool {
+        compare_values(&self.0, &other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for OrderedValue {}
+
+impl PartialOrd for OrderedValue {
+    fn partial_cmp(&self, other: &OrderedValue) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedValue {
+    fn cmp(&self, other: &OrderedValue) -> Ordering {
+        compare_values(&self.0, &other.0)
+    }
+}
+
+impl Hash for OrderedValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(&self.0, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+
+    use model::TimeStamp;
+    use value::*;
+
+    #[test]
+    fn accessors() {
+        let record = Value::from(vec![
+            (Value::from("time"), Value::from(1470000000)),
+            (Value::from("name"), Value::from("foo")),
+            (Value::from("score"), Value::from(1.5)),
+            (Value::from("tags"), Value::from(vec![Value::from("a")])),
+        ]);
+        assert_eq!(Some(1470000000), record.get("time").unwrap().as_i64());
+        assert_eq!(Some("foo"), record.get("name").and_then(|x| x.as_str()));
+        assert_eq!(Some(1.5), record.get("score").and_then(|x| x.as_f64()));
+        assert_eq!(1, record.get("tags").unwrap().as_array().unwrap().len());
+        assert_eq!(None, record.get("unknown"));
+        assert_eq!(None, Value::from(-1).as_u64());
+        assert_eq!(None, Value::from(u64::max_value()).as_i64());
+        assert!(Value::from(None::<i64>).is_nil());
+    }
+
+    #[test]
+    fn try_from() {
+        assert_eq!(Ok(42), i32::try_from(&Value::from(42u64)));
+        assert!(u8::try_from(&Value::from(256)).is_err());
+        assert!(i64::try_from(&Value::from("42")).is_err());
+        assert_eq!(Ok(None), Option::<i64>::try_from(&Value::Nil));
+        assert_eq!(Ok(Some(42)), Option::<i64>::try_from(&Value::from(42)));
+        assert_eq!(Ok("foo".to_string()), String::try_from(&Value::from("foo")));
+        assert_eq!(
+            Ok(vec![1u8, 2]),
+            Vec::<u8>::try_from(&Value::from(&[1u8, 2][..]))
+        );
+        assert_eq!(Ok(true), bool::try_from(&Value::from(true)));
+        assert_eq!(Ok(2.0), f64::try_from(&Value::from(2)));
+
+        let datetime = Utc.timestamp_opt(1470000000, 0).unwrap();
+        assert_eq!(Value::from(1470000000), Value::from(datetime));
+        assert_eq!(
+            Ok(datetime),
+            DateTime::<Utc>::try_from(&Value::from(1470000000))
+        );
+        assert_eq!(
+            Ok(datetime),
+            DateTime::<Utc>::try_from(&Value::from("2016-07-31 21:20:00 UTC"))
+        );
+        assert_eq!(
+            Ok(TimeStamp::from_unix_time(1470000000).unwrap()),
+            TimeStamp::try_from(&Value::from("2016-07-31T21:20:00Z"))
+        );
+    }
+
+    #[test]
+    fn write_msgpack() {
+        let value = Value::from(vec![
+            (Value::from("time"), Value::from(1470000000)),
+            (Value::from("big"), Value::from(u64::max_value())),
+            (Value::from("neg"), Value::from(-1)),
+            (Value::from("f32"), Value::from(1.5f32)),
+            (Value::from("f64"), Value::from(2.5)),
+            (Value::from("bin"), Value::from(vec![0u8, 1])),
+            (
+                Value::from("array"),
+                Value::from(vec![Value::Nil, Value::from(true)]),
+            ),
+            (Value::from("ext"), Value::Ext(1, vec![2, 3, 4, 5])),
+        ]);
+        let mut buf = vec![];
+        value.write_msgpack(&mut buf).unwrap();
+
+        let mut expected = vec![];
+        ::rmpv::encode::write_value(&mut expected, &::rmpv::Value::from(value.clone())).unwrap();
+        assert_eq!(expected, buf);
+        assert_eq!(
+            value,
+            Value::from(::rmpv::decode::read_value(&mut &buf[..]).unwrap())
+        );
+    }
+
+    #[test]
+    fn display() {
+        let value = Value::from(vec![
+            (Value::from("s"), Value::from("a\"b\n")),
+            (
+                Value::from(1),
+                Value::from(vec![Value::from(1.0), Value::Nil]),
+            ),
+            (Value::from("bin"), Value::from(vec![1u8, 255])),
+            (Value::from("nan"), Value::from(::std::f64::NAN)),
+        ]);
+        assert_eq!(
+            r#"{"s":"a\"b\n",1:[1.0,null],"bin":0x01ff,"nan":NaN}"#,
+            value.to_string()
+        );
+        assert_eq!("0.1", Value::from(0.1f32).to_string());
+        assert_eq!("1.0", Value::from(1.0f32).to_string());
+    }
+
+    #[test]
+    fn canonical_json() {
+        let a = Value::from(vec![
+            (Value::from("b"), Value::from(1.0)),
+            (Value::from("a"), Value::from(vec![1u8])),
+            (Value::from(2), Value::from(::std::f64::INFINITY)),
+        ]);
+        assert_eq!(r#"{"2":null,"a":[1],"b":1}"#, a.to_canonical_json());
+        assert_eq!(
+            r#"["x",-0.5,0,{"2":null,"a":[1],"b":1}]"#,
+            row_to_canonical_json(&[Value::from("x"), Value::from(-0.5f32), Value::from(-0.0), a])
+        );
+        assert_eq!("0.1", Value::from(0.1f32).to_canonical_json());
+        assert_eq!("0", Value::from(-0.0f32).to_canonical_json());
+    }
+
+    #[test]
+    fn ordered_value() {
+        let mut set = HashSet::new();
+        set.insert(OrderedValue(Value::from(::std::f64::NAN)));
+        set.insert(OrderedValue(Value::from(-::std::f64::NAN)));
+        set.insert(OrderedValue(Value::from(0.0)));
+        set.insert(OrderedValue(Value::from(-0.0)));
+        set.insert(OrderedValue(Value::from(1.5f32)));
+        set.insert(OrderedValue(Value::from(1.5)));
+        set.insert(OrderedValue(Value::Integer(Integer::U64(1))));
+        set.insert(OrderedValue(Value::Integer(Integer::I64(1))));
+        assert_eq!(4, set.len());
+
+        // Maps are equal regardless of the order of their entries
+        let ab = Value::from(vec![
+            (Value::from("a"), Value::from(1)),
+            (Value::from("b"), Value::from(2)),
+        ]);
+        let ba = Value::from(vec![
+            (Value::from("b"), Value::from(2)),
+            (Value::from("a"), Value::from(1)),
+        ]);
+        assert_eq!(ab.to_canonical_json(), ba.to_canonical_json());
+        assert_eq!(OrderedValue(ab.clone()), OrderedValue(ba.clone()));
+        let mut set = HashSet::new();
+        set.insert(OrderedValue(ab));
+        set.insert(OrderedValue(ba));
+        assert_eq!(1, set.len());
+
+        let mut values: Vec<OrderedValue> = vec![
+            Value::from("a"),
+            Value::from(::std::f64::NAN),
+            Value::from(2),
+            Value::Nil,
+            Value::from(::std::f64::INFINITY),
+            Value::from(-1),
+            Value::from(vec![Value::from(1), Value::from(2)]),
+            Value::from(vec![Value::from(1)]),
+        ]
+        .into_iter()
+        .map(OrderedValue::from)
+        .collect();
+        values.sort();
+        assert_eq!(
+            "null,-1,2,Infinity,NaN,\"a\",[1],[1,2]",
+            values
+                .into_iter()
+                .map(|x| x.into_inner().to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        );
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn serde_json() {
+        let json: ::serde_json::Value =
+            ::serde_json::from_str(r#"{"a":[1,-2,1.5,"x",null,true]}"#).unwrap();
+        let value = Value::from(json.clone());
+        assert_eq!(
+            Some(&Value::from(vec![
+                Value::from(1),
+                Value::from(-2),
+                Value::from(1.5),
+                Value::from("x"),
+                Value::Nil,
+                Value::from(true),
+            ])),
+            value.get("a")
+        );
+        assert_eq!(json, ::serde_json::Value::from(value));
+        assert_eq!(
+            r#"{"1":[1,2]}"#,
+            ::serde_json::Value::from(Value::from(vec![(
+                Value::from(1),
+                Value::from(vec![1u8, 2])
+            )]))
+            .to_string()
+        );
+    }
+}
@@ -5,7 +5,6 @@ use std::convert::TryInto;
 use std::env;
 use std::error::Error;
 use std::thread;
-use std::sync::{Arc, Mutex};
 use std::time;
 use std::time::SystemTime;
 use std::vec::Vec;
@@ -18,7 +17,7 @@ use td_client::value::*;
 
 fn test_with_database(client: &Client<DefaultRequestExecutor>, database: &str) -> Result<(), Box<dyn Error>> {
     // Prepare database
-    if client.databases()?.iter().any(|db| db.name == database) {
+    if client.database_exists(database)? {
         client.delete_database(database)?;
     }
     client.create_database(database)?;
@@ -31,7 +30,7 @@ fn test_with_database(client: &Client<DefaultRequestExecutor>, database: &str) -
         s
     };
 
-    if client.tables(database)?.iter().any(|tbl| tbl.name == table) {
+    if client.table_exists(database, &table)? {
         client.delete_table(database, &table)?;
     }
     client.create_table(database, &table)?;
@@ -66,27 +65,17 @@ fn test_with_database(client: &Client<DefaultRequestExecutor>, database: &str) -
             Err("Retried over. Imported records are still unvisible")?;
         }
 
-        let job_id = client.issue_job(
+        let result = client.query(
             QueryType::Presto, database,
             format!("select count(1) as cnt from {}", table).as_str(),
-            None, None, None, None, None)?;
+            &QueryOptions::new())?;
 
-        println!("Waiting the job: {}", job_id);
+        println!("The job finished: {}", result.job_id);
 
-        client.wait_job(job_id, None)?;
-
-        println!("The job finished: {}", job_id);
-
-        let records = Arc::new(Mutex::new(Vec::new()));
-        client.each_row_in_job_result(
-            job_id,
-            &|xs| {
-                records.lock().unwrap().push(xs);
-                true
-            })?;
+        let records = result.rows.collect::<Result<Vec<Vec<Value>>, _>>()?;
 
         let expected = &Value::Integer(Integer::I64(2));
-        let actual = &records.lock().unwrap()[0][0];
+        let actual = &records[0][0];
         if expected == actual {
             break;
         }